@@ -1,28 +1,15 @@
 use bit_set::BitSet;
-use core::str;
-use petgraph::graphmap::{DiGraphMap, GraphMap};
+use clap::{Parser, ValueEnum};
 use rayon::prelude::*;
-use scan_fmt::scan_fmt;
 use std::{
-    cell::{self, RefCell}, env,
+    cell::RefCell,
     error::Error,
     fs::File,
-    io::{BufRead, BufReader, Read},
+    io::{BufRead, BufReader},
+    time::Instant,
 };
 use thiserror::Error;
 
-#[derive(Debug, Clone, Error)]
-#[error("Command line error {0}")]
-struct CommandLineError(String);
-
-impl CommandLineError {
-    fn new(msg: &str) -> Self {
-        CommandLineError {
-            0: String::from(msg),
-        }
-    }
-}
-
 #[derive(Debug, Clone, Error, PartialEq)]
 enum PuzzleError {
     #[error("Unexpected character in puzzle at ({0},{1})")]
@@ -33,6 +20,8 @@ enum PuzzleError {
     MultipleGuards(usize, usize),
     #[error("The guard returned to the initial position and direction")]
     ReturnedToInitialPosition,
+    #[error("Strategies disagree on the loop count: {0}")]
+    StrategiesDisagree(String),
 }
 
 
@@ -83,7 +72,7 @@ impl ObstacleMatrix {
         let (row, col) = (&mut self.rows[pos.0], &mut self.cols[pos.1]);
 
         let bad_insert = |dim: &[usize], val| {
-            dim.last().map_or(false, |&v| v >= val)
+            dim.last().is_some_and(|&v| v >= val)
         };
 
         if bad_insert(row, pos.1) ||
@@ -94,8 +83,6 @@ impl ObstacleMatrix {
 
         row.push(pos.1);
         col.push(pos.0);
-
-        ()
     }
 
     fn next_pos(&self, pos: &Position) -> Option<Position> {
@@ -110,7 +97,7 @@ impl ObstacleMatrix {
 
         let dim_pos = search_dim.binary_search(&dim_val).expect_err("Expected not to be exactly at a obstacle");
 
-        assert!(search_dim.get(dim_pos).map_or(true, |&v| v > dim_val));
+        assert!(search_dim.get(dim_pos).is_none_or(|&v| v > dim_val));
 
         let new_dim_val =
         if !dim_dir {
@@ -165,28 +152,26 @@ fn override_next_pos(old_pos: &Position, new_pos: &Option<Position>, override_po
             return *new_pos;
         }
 
-        return Some(
+        Some(
             Position::new(
                 (get_updated_val(old_pos.location.0, new_pos.map(|p| p.location.0), override_pos.0, v.0)?,
                           override_pos.1),
                           new_direction
             )
-        );
+        )
 
     } else {
-        if (old_pos.location.0 != override_pos.0) {
+        if old_pos.location.0 != override_pos.0 {
             return *new_pos;
         }
 
-        return Some(
+        Some(
             Position::new(
                 (override_pos.0,
                           get_updated_val(old_pos.location.1, new_pos.map(|p| p.location.1), override_pos.1, v.1)?),
                           new_direction
                 )
-                        
-            
-            );
+            )
     }
 }
 
@@ -205,7 +190,7 @@ impl Puzzle {
         assert!(pos.0 < self.rows);
         assert!(pos.1 < self.cols);
 
-        return pos.0 * self.cols + pos.1;
+        pos.0 * self.cols + pos.1
     }
 
     fn is_obstacle(&self, pos: &(usize, usize)) -> bool {
@@ -231,9 +216,9 @@ fn parse_puzzle<'a>(
     let mut guard_loc = None;
     let mut obstacle_matrix = ObstacleMatrix::new(rows, cols);
 
-    for i in 0..rows {
-        for j in 0..cols {
-            match (map[i][j]) {
+    for (i, line) in map.iter().enumerate() {
+        for (j, &c) in line.iter().enumerate() {
+            match c {
                 b'.' => {}
                 b'#' => {
                     obstacle_set.insert(i * cols + j);
@@ -255,13 +240,13 @@ fn parse_puzzle<'a>(
         Err(PuzzleError::NoGuard)?
     };
 
-    return Ok(Puzzle {
+    Ok(Puzzle {
         rows,
         cols,
         guard_loc,
         obstacle_set,
         obstacle_matrix,
-    });
+    })
 }
 
 
@@ -287,7 +272,7 @@ enum IterationResult {
     ReturnedToInitialPosition,
 }
 
-fn IterateThroughPuzzle<'a>(
+fn iterate_through_puzzle(
     puzzle: &Puzzle,
     f: &mut impl FnMut((usize, usize), usize) -> bool,
 ) -> IterationResult {
@@ -300,11 +285,7 @@ fn IterateThroughPuzzle<'a>(
         return IterationResult::CallbackReturned;
     }
 
-    loop {
-        let Some(new_loc) = new_loc(puzzle, &cur_loc, cur_dir) else {
-            break;
-        };
-
+    while let Some(new_loc) = new_loc(puzzle, &cur_loc, cur_dir) {
         if puzzle.is_obstacle(&new_loc) {
             cur_dir = change_direction(cur_dir);
         } else {
@@ -369,7 +350,7 @@ fn part2_checkone(
 
     assert!(new_puzzle.obstacle_set.insert(puzzle.cell_index(new_obstacle)));
 
-    let res = IterateThroughPuzzle(&new_puzzle, &mut mark_visited_with_directions);
+    let res = iterate_through_puzzle(new_puzzle, &mut mark_visited_with_directions);
 
     assert!(new_puzzle.obstacle_set.remove(puzzle.cell_index(new_obstacle)));
 
@@ -413,17 +394,354 @@ fn part2_checkone_jumping(
     }
 }
 
-fn main() -> Result<(), Box<dyn Error>> {
-    let mut args = env::args();
-    if args.len() > 2 {
-        return Err(CommandLineError::new("Unexpected arg count.").into());
+/// For every (cell, direction), where the guard ends up after walking to the next obstacle
+/// and turning right, or None if it walks off the map. Obstacles can be added on top of the
+/// puzzle's own and later reset, touching only the cells in line with each new obstacle.
+#[derive(Debug, Clone)]
+struct JumpTable {
+    rows: usize,
+    cols: usize,
+    blocked: BitSet,
+    next: Vec<Option<Position>>,
+
+    added: Vec<(usize, usize)>,
+    undo: Vec<(usize, Option<Position>)>,
+
+    // Generation stamps for loop detection, so a walk doesn't have to clear a set.
+    seen: Vec<u32>,
+    generation: u32,
+}
+
+impl JumpTable {
+    fn new(puzzle: &Puzzle) -> Self {
+        let slots = puzzle.rows * puzzle.cols * DIRECTIONS.len();
+        let mut table = JumpTable {
+            rows: puzzle.rows,
+            cols: puzzle.cols,
+            blocked: puzzle.obstacle_set.clone(),
+            next: vec![None; slots],
+            added: Vec::new(),
+            undo: Vec::new(),
+            seen: vec![0; slots],
+            generation: 0,
+        };
+
+        // Every cell that can walk into an obstacle is swept from exactly one obstacle per
+        // direction, so building the table is linear in the grid size.
+        for o in puzzle.obstacle_set.iter() {
+            table.sweep_from((o / puzzle.cols, o % puzzle.cols), false);
+        }
+
+        table
+    }
+
+    fn slot(&self, pos: &Position) -> usize {
+        (pos.location.0 * self.cols + pos.location.1) * DIRECTIONS.len() + pos.direction
+    }
+
+    fn step(&self, loc: (usize, usize), direction: usize) -> Option<(usize, usize)> {
+        let v = DIRECTIONS[direction];
+        let loc = (loc.0.wrapping_add_signed(v.0.into()), loc.1.wrapping_add_signed(v.1.into()));
+        (loc.0 < self.rows && loc.1 < self.cols).then_some(loc)
+    }
+
+    /// Points every cell that walks into the obstacle at `o` at the turn in front of it.
+    fn sweep_from(&mut self, o: (usize, usize), log_undo: bool) {
+        for direction in 0..DIRECTIONS.len() {
+            let back = (direction + 2) % DIRECTIONS.len();
+            let Some(turn_at) = self.step(o, back) else {
+                continue;
+            };
+
+            let stop = Some(Position::new(turn_at, change_direction(direction)));
+            let mut cell = Some(turn_at);
+            while let Some(loc) = cell.filter(|loc| !self.blocked.contains(loc.0 * self.cols + loc.1)) {
+                let slot = self.slot(&Position::new(loc, direction));
+                if log_undo {
+                    self.undo.push((slot, self.next[slot]));
+                }
+                self.next[slot] = stop;
+                cell = self.step(loc, back);
+            }
+        }
+    }
+
+    fn next_pos(&self, pos: &Position) -> Option<Position> {
+        self.next[self.slot(pos)]
+    }
+
+    /// Adds an obstacle until the next `reset`. Cells that are already blocked are ignored.
+    fn add_obstacle(&mut self, o: (usize, usize)) {
+        if self.blocked.insert(o.0 * self.cols + o.1) {
+            self.added.push(o);
+            self.sweep_from(o, true);
+        }
+    }
+
+    /// Removes every obstacle added since the table was built.
+    fn reset(&mut self) {
+        for (slot, next) in self.undo.drain(..).rev() {
+            self.next[slot] = next;
+        }
+
+        for o in self.added.drain(..) {
+            self.blocked.remove(o.0 * self.cols + o.1);
+        }
+    }
+
+    /// Whether the guard, starting at `start`, ends up walking in a loop.
+    fn walk_loops(&mut self, start: Position) -> bool {
+        self.generation += 1;
+
+        let mut pos = start;
+        while let Some(next) = self.next_pos(&pos) {
+            let slot = self.slot(&next);
+            if self.seen[slot] == self.generation {
+                return true;
+            }
+
+            self.seen[slot] = self.generation;
+            pos = next;
+        }
+
+        false
+    }
+
+    /// Whether adding all of `obstacles` at once traps the guard starting at `start` in a loop.
+    fn creates_loop(&mut self, start: Position, obstacles: &[(usize, usize)]) -> bool {
+        for &o in obstacles {
+            self.add_obstacle(o);
+        }
+
+        let looped = self.walk_loops(start);
+        self.reset();
+        looped
+    }
+}
+
+/// Every position where one new obstacle makes the guard loop. Each candidate is tried the
+/// first time the guard would walk into it, starting the walk from there since the path
+/// before is unchanged. That costs one table update and one walk over turns per cell on
+/// the path.
+fn part2_loop_positions(puzzle: &Puzzle, table: &mut JumpTable) -> Vec<(usize, usize)> {
+    let mut tried = BitSet::with_capacity(puzzle.rows * puzzle.cols);
+    let mut positions = Vec::new();
+    let mut last = Position::new(puzzle.guard_loc, 0);
+
+    tried.insert(puzzle.cell_index(&puzzle.guard_loc));
+    iterate_through_puzzle(puzzle, &mut |loc, dir| {
+        if tried.insert(puzzle.cell_index(&loc)) && table.creates_loop(Position::new(last.location, dir), &[loc]) {
+            positions.push(loc);
+        }
+
+        last = Position::new(loc, dir);
+        true
+    });
+
+    positions.sort();
+    positions
+}
+
+const VERTICAL: u8 = 1;
+const HORIZONTAL: u8 = 2;
+
+/// Walks the guard with an optional extra obstacle, marking each cell with the axes it was
+/// crossed along. Both ends of every step are marked so turns show up as crossings. Also
+/// returns whether the guard ended up in a loop.
+fn trace_path(puzzle: &Puzzle, extra_obstacle: Option<(usize, usize)>) -> (Vec<u8>, bool) {
+    let mut puzzle = puzzle.clone();
+    if let Some(o) = extra_obstacle {
+        puzzle.obstacle_set.insert(puzzle.cell_index(&o));
+    }
+
+    let mut marks = vec![0u8; puzzle.rows * puzzle.cols];
+    let mut seen = BitSet::with_capacity(puzzle.rows * puzzle.cols * DIRECTIONS.len());
+    let mut last = puzzle.guard_loc;
+    let res = iterate_through_puzzle(&puzzle, &mut |loc, dir| {
+        let axis = if DIRECTIONS[dir].0 == 0 { HORIZONTAL } else { VERTICAL };
+        marks[puzzle.cell_index(&last)] |= axis;
+        marks[puzzle.cell_index(&loc)] |= axis;
+        last = loc;
+        seen.insert(puzzle.cell_index(&loc) * DIRECTIONS.len() + dir)
+    });
+
+    (marks, res != IterationResult::Escaped)
+}
+
+/// Draws the grid with the guard's path as `|`, `-` and `+`, and the extra obstacle as `O`.
+fn render_path(puzzle: &Puzzle, marks: &[u8], extra_obstacle: Option<(usize, usize)>) -> String {
+    let mut out = String::with_capacity((puzzle.cols + 1) * puzzle.rows);
+    for i in 0..puzzle.rows {
+        for j in 0..puzzle.cols {
+            let pos = (i, j);
+            out.push(
+                if Some(pos) == extra_obstacle {
+                    'O'
+                } else if puzzle.is_obstacle(&pos) {
+                    '#'
+                } else if pos == puzzle.guard_loc {
+                    '^'
+                } else {
+                    match marks[puzzle.cell_index(&pos)] {
+                        VERTICAL => '|',
+                        HORIZONTAL => '-',
+                        0 => '.',
+                        _ => '+',
+                    }
+                });
+        }
+        out.push('\n');
+    }
+
+    out
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Strategy {
+    /// Step the guard cell by cell for every candidate obstacle.
+    Serial,
+    /// The serial walk spread over rows with rayon.
+    Rayon,
+    /// Jump between obstacles with the obstacle matrix.
+    Jumping,
+    /// The jumping walk spread over rows with rayon.
+    RayonJumping,
+    /// Incremental jump table, trying each cell where the guard first reaches it.
+    Table,
+}
+
+impl Strategy {
+    fn name(self) -> String {
+        self.to_possible_value().unwrap().get_name().to_string()
+    }
+}
+
+#[derive(Parser, Debug)]
+#[command(about)]
+/// Count the places an obstacle would trap the guard in a loop.
+struct Args {
+    #[arg(default_value = "input_sample.txt")]
+    input_file: String,
+
+    /// Which part 2 implementation to run.
+    #[arg(short, long, value_enum, default_value_t = Strategy::Table)]
+    strategy: Strategy,
+
+    /// Run every strategy, check that they agree and print a timing table.
+    #[arg(short, long, default_value_t = false)]
+    compare: bool,
+
+    /// Draw the guard's path.
+    #[arg(short, long, default_value_t = false)]
+    render: bool,
+
+    /// Draw the loop caused by each obstacle position that creates one.
+    #[arg(long, default_value_t = false)]
+    render_loops: bool,
+
+    /// Print the obstacle positions that create a loop, one `row,col` per line.
+    #[arg(short, long, default_value_t = false)]
+    list_loops: bool,
+}
+
+/// Scratch state for checking candidates, one per rayon worker thread.
+#[derive(Debug)]
+struct TlsData {
+    new_puzzle: Puzzle,
+    visited_with_directions: BitSet
+}
+
+impl TlsData {
+    fn new(puzzle: &Puzzle) -> Self {
+        TlsData {
+            new_puzzle: puzzle.clone(),
+            visited_with_directions: BitSet::with_capacity(puzzle.rows * puzzle.cols * DIRECTIONS.len()),
+        }
+    }
+}
+
+/// Counts the cells where `check` finds a loop, one row per rayon task. The scratch state
+/// is kept per thread so it isn't reallocated for every row.
+fn count_loops_parallel(puzzle: &Puzzle, check: impl Fn(&(usize, usize), &mut TlsData) -> bool + Sync) -> usize {
+    #[derive(Debug)]
+    enum TlsState {
+        Uninitialized,
+        Initialized(TlsData)
+    }
+
+    thread_local! {
+        static TLS_STATE: RefCell<TlsState> = const { RefCell::new(TlsState::Uninitialized) }
+    }
+
+    (0..puzzle.rows).into_par_iter().map(
+        |i| -> usize {
+            TLS_STATE.with_borrow_mut(
+                |state| {
+                    if matches!(state, TlsState::Uninitialized) {
+                        *state = TlsState::Initialized(TlsData::new(puzzle));
+                    }
+
+                    let TlsState::Initialized(data) = state else {panic!("Unexpected tls state")};
+
+                    (0..puzzle.cols).filter(|&j| check(&(i, j), data)).count()
+                }
+            )
+        }
+    ).sum()
+}
+
+fn count_loops(puzzle: &Puzzle, visited: &BitSet, strategy: Strategy) -> usize {
+    let cells = || (0..puzzle.rows).flat_map(|i| (0..puzzle.cols).map(move |j| (i, j)));
+
+    match strategy {
+        Strategy::Serial => {
+            let mut data = TlsData::new(puzzle);
+            cells().filter(|pos| part2_checkone(puzzle, pos, visited, &mut data.new_puzzle, &mut data.visited_with_directions)).count()
+        }
+        Strategy::Rayon => count_loops_parallel(puzzle, |pos, data| {
+            part2_checkone(puzzle, pos, visited, &mut data.new_puzzle, &mut data.visited_with_directions)
+        }),
+        Strategy::Jumping => {
+            let mut data = TlsData::new(puzzle);
+            cells().filter(|pos| part2_checkone_jumping(puzzle, pos, visited, &mut data.visited_with_directions)).count()
+        }
+        Strategy::RayonJumping => count_loops_parallel(puzzle, |pos, data| {
+            part2_checkone_jumping(puzzle, pos, visited, &mut data.visited_with_directions)
+        }),
+        Strategy::Table => part2_loop_positions(puzzle, &mut JumpTable::new(puzzle)).len(),
+    }
+}
+
+/// Runs every strategy, prints how long each took relative to the serial one and fails if
+/// any of them disagree.
+fn compare_strategies(puzzle: &Puzzle, visited: &BitSet) -> Result<usize, PuzzleError> {
+    let results = Strategy::value_variants().iter().map(|&strategy| {
+        let start = Instant::now();
+        let count = count_loops(puzzle, visited, strategy);
+        (strategy, count, start.elapsed())
+    }).collect::<Vec<_>>();
+
+    let baseline = results[0].2.as_secs_f64();
+    println!("{:<14} {:>8} {:>12} {:>8}", "strategy", "loops", "time", "speedup");
+    for (strategy, count, elapsed) in results.iter() {
+        println!("{:<14} {count:>8} {:>12} {:>7.2}x", strategy.name(), format!("{elapsed:.2?}"), baseline / elapsed.as_secs_f64());
+    }
+
+    let count = results[0].1;
+    if results.iter().any(|r| r.1 != count) {
+        let counts = results.iter().map(|(strategy, count, _)| format!("{}={count}", strategy.name())).collect::<Vec<_>>();
+        return Err(PuzzleError::StrategiesDisagree(counts.join(", ")));
     }
 
-    let file_name = args.nth(1).unwrap_or("input_sample.txt".into());
+    Ok(count)
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let args = Args::parse();
 
-    println!("Opening file {}", file_name);
+    println!("Opening file {}", args.input_file);
 
-    let reader = BufReader::new(File::open(file_name)?);
+    let reader = BufReader::new(File::open(&args.input_file)?);
     let lines = reader.lines();
 
     let puzzle = parse_puzzle(lines)?;
@@ -434,7 +752,7 @@ fn main() -> Result<(), Box<dyn Error>> {
         true
     };
 
-    if IterateThroughPuzzle(&puzzle, &mut mark_visited) != IterationResult::Escaped {
+    if iterate_through_puzzle(&puzzle, &mut mark_visited) != IterationResult::Escaped {
         Err(PuzzleError::ReturnedToInitialPosition)?
     }
 
@@ -442,103 +760,106 @@ fn main() -> Result<(), Box<dyn Error>> {
 
     dbg!(visited_count);
 
-    let mut new_puzzle = puzzle.clone();
-    let mut cycle_position_count = 0usize;
+    if args.render {
+        print!("{}", render_path(&puzzle, &trace_path(&puzzle, None).0, None));
+    }
 
-    let mut visited_with_directions =
-        BitSet::with_capacity(puzzle.rows * puzzle.cols * DIRECTIONS.len());
+    if args.render_loops || args.list_loops {
+        let positions = part2_loop_positions(&puzzle, &mut JumpTable::new(&puzzle));
+        if args.list_loops {
+            println!("row,col");
+            for (i, j) in positions.iter() {
+                println!("{i},{j}");
+            }
+        }
 
-    let mut serial_timer = stopwatch::Stopwatch::start_new();
-    for i in 0..puzzle.rows {
-        for j in 0..puzzle.cols {
-            let pos = (i, j);
-            cycle_position_count += part2_checkone(
-                &puzzle,
-                &pos,
-                &visited,
-                &mut new_puzzle,
-                &mut visited_with_directions,
-            ) as usize;
+        if args.render_loops {
+            for &o in positions.iter() {
+                let (marks, looped) = trace_path(&puzzle, Some(o));
+                assert!(looped, "Obstacle at {o:?} was expected to cause a loop");
+                println!("Obstacle at {o:?}:");
+                println!("{}", render_path(&puzzle, &marks, Some(o)));
+            }
         }
     }
 
-    serial_timer.stop();
-    
-    dbg!(cycle_position_count);
-    
-    let mut parallel_timer = stopwatch::Stopwatch::start_new();
+    let cycle_position_count = if args.compare {
+        compare_strategies(&puzzle, &visited)?
+    } else {
+        count_loops(&puzzle, &visited, args.strategy)
+    };
 
-    let par_cycle_count: usize = (0..puzzle.rows).into_par_iter().map(
-        |i| -> usize {
-            #[derive(Debug)]
-            struct TlsData {
-                new_puzzle: Puzzle,
-                visited_with_directions: BitSet
-            }
+    dbg!(cycle_position_count);
 
-            #[derive(Debug)]
-            enum TlsState {
-                Uninitialized,
-                Initialized(TlsData)
-            }
+    Ok(())
+}
 
-            thread_local! {
-                static TLS_STATE: RefCell<TlsState> = RefCell::new(TlsState::Uninitialized)
-            }
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-            let mut cycle_count = 0usize;
-            
-            TLS_STATE.with_borrow_mut(
-                |state| {
-                    if matches!(state, TlsState::Uninitialized) {
-                        let tls_data = TlsData{new_puzzle: puzzle.clone(),
-                            visited_with_directions:  BitSet::with_capacity(puzzle.rows * puzzle.cols * DIRECTIONS.len())};
-                            
-                            *state = TlsState::Initialized(tls_data);
-                        }
-                        
-                        let TlsState::Initialized(data) = state else {panic!("Unexpected tls state")};
-                        
-                        for j in 0..puzzle.cols {
-                            let pos = (i, j);
-
-                            cycle_count += part2_checkone(&puzzle, &pos, &visited, &mut data.new_puzzle, &mut data.visited_with_directions) as usize;
-                        }
-                    }
-                );
+    fn sample() -> Puzzle {
+        parse_puzzle(include_str!("../input_sample.txt").lines().map(Ok::<_, std::io::Error>)).unwrap()
+    }
 
-            cycle_count
-        }
-    ).sum();
-
-    parallel_timer.stop();
-    
-    dbg!(par_cycle_count);
-    
-    let mut jumping_timer = stopwatch::Stopwatch::start_new();
-    let mut jumping_cycle_count = 0usize;
-    for i in 0..puzzle.rows {
-        for j in 0..puzzle.cols {
-            let pos = (i, j);
-            jumping_cycle_count += part2_checkone_jumping(
-                &puzzle,
-                &pos,
-                &visited,
-                &mut visited_with_directions,
-            ) as usize;
+    /// Walks the guard step by step with `obstacles` added to the puzzle.
+    fn loops_with(puzzle: &Puzzle, obstacles: &[(usize, usize)]) -> bool {
+        let mut puzzle = puzzle.clone();
+        for o in obstacles {
+            puzzle.obstacle_set.insert(puzzle.cell_index(o));
         }
+
+        let mut seen = BitSet::new();
+        iterate_through_puzzle(&puzzle, &mut |pos, dir| seen.insert(puzzle.cell_index(&pos) * DIRECTIONS.len() + dir))
+            != IterationResult::Escaped
+    }
+
+    #[test]
+    fn renders_loop() {
+        let puzzle = sample();
+        let (marks, looped) = trace_path(&puzzle, Some((6, 3)));
+        assert!(looped);
+        assert_eq!(render_path(&puzzle, &marks, Some((6, 3))), concat!(
+            "....#.....\n",
+            "....+---+#\n",
+            "....|...|.\n",
+            "..#.|...|.\n",
+            "....|..#|.\n",
+            "....|...|.\n",
+            ".#.O^---+.\n",
+            "........#.\n",
+            "#.........\n",
+            "......#...\n",
+        ));
+
+        assert!(!trace_path(&puzzle, None).1);
     }
-    jumping_timer.stop();
 
-    dbg!(jumping_cycle_count);
+    #[test]
+    fn jump_table_finds_loop_positions() {
+        let puzzle = sample();
+        let mut table = JumpTable::new(&puzzle);
+        assert_eq!(part2_loop_positions(&puzzle, &mut table), [(6, 3), (7, 6), (7, 7), (8, 1), (8, 3), (9, 7)]);
+    }
 
-    let serial_time = serial_timer.elapsed().as_secs_f32();
-    let parallel_time = parallel_timer.elapsed().as_secs_f32();
-    let jumping_time = jumping_timer.elapsed().as_secs_f32();
-    dbg!(serial_time);
-    dbg!(parallel_time);
-    dbg!(serial_time / parallel_time);
-    dbg!(jumping_time);
+    #[test]
+    fn jump_table_batches_match_simulation() {
+        let puzzle = sample();
+        let mut table = JumpTable::new(&puzzle);
+        let fresh = table.next.clone();
+        let start = Position::new(puzzle.guard_loc, 0);
+
+        let open = (0..puzzle.rows)
+            .flat_map(|i| (0..puzzle.cols).map(move |j| (i, j)))
+            .filter(|o| !puzzle.is_obstacle(o) && *o != puzzle.guard_loc)
+            .collect::<Vec<_>>();
+
+        for (i, &a) in open.iter().enumerate() {
+            for &b in open[i..].iter().step_by(7) {
+                assert_eq!(table.creates_loop(start, &[a, b]), loops_with(&puzzle, &[a, b]), "{a:?} {b:?}");
+            }
+        }
 
-    Ok(())
+        assert_eq!(table.next, fresh);
+    }
 }
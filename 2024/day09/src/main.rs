@@ -1,11 +1,29 @@
 use std::cmp::min;
+use std::cmp::Reverse;
 use std::collections::BTreeMap;
+use std::collections::BinaryHeap;
 use std::collections::VecDeque;
 use std::error::Error;
-use std::env;
+use std::fmt::Write as _;
 use std::fs;
+use std::io;
+use std::io::BufRead;
 use std::time::Instant;
+use clap::{Parser, ValueEnum};
 use itertools::Itertools;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+enum PuzzleError {
+    #[error("unexpected '{0}' at byte {1} of the disk map")]
+    UnexpectedCharacter(char, usize),
+
+    #[error("length at byte {0} of the disk map is too long")]
+    LengthOverflow(usize),
+
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
 
 fn time_it<T>(name: &str, f: impl FnOnce() -> T) -> T {
     let start = Instant::now();
@@ -18,6 +36,9 @@ fn time_it<T>(name: &str, f: impl FnOnce() -> T) -> T {
     ret
 }
 
+// The digit-only solvers from before the disk model, kept to check it against.
+
+#[cfg(test)]
 fn compute_score(id: usize, offset: usize, count: u8) -> u64 {
     let id = id as u64;
     let offset = offset as u64;
@@ -26,6 +47,7 @@ fn compute_score(id: usize, offset: usize, count: u8) -> u64 {
     id * (offset..(offset + count)).sum::<u64>()
 }
 
+#[cfg(test)]
 fn solve_part1<'a>(
     mut files: impl DoubleEndedIterator<Item = (usize, &'a u8)>,
     mut frees: impl Iterator<Item = &'a u8>,
@@ -79,27 +101,14 @@ fn solve_part1<'a>(
     sum
 }
 
+#[cfg(test)]
 struct File {
     offset:usize,
     id:usize,
     file_size:usize
 }
 
-fn print_file_map(file_map: &[File]) {
-    let mut offset = 0usize;
-
-    file_map.iter().for_each(|f| {
-        if (offset < f.offset) {
-            print!("{}", ".".repeat(f.offset - offset).to_string());
-        }
-
-        print!("{}", f.id.to_string().repeat(f.file_size));
-        offset = f.offset + f.file_size;
-    });
-
-    println!();
-}
-
+#[cfg(test)]
 fn solve_part2(
     data: &[u8]
     ) -> u64
@@ -107,18 +116,15 @@ fn solve_part2(
     let mut file_map = Vec::new();
     let mut free_space_map = vec![VecDeque::new();10];
 
-    let mut id = 0usize;
     let mut offset = 0usize;
-    for file_and_free in data.chunks(2) {
+    for (id, file_and_free) in data.chunks(2).enumerate() {
         let file_size = file_and_free[0] as usize;
         file_map.push(File{offset, id, file_size});
 
         // println!("{id}: {file_and_free:?}");
 
-        offset += file_size as usize;
+        offset += file_size;
 
-        id += 1;
-        
         if let Some(&free_count) = file_and_free.get(1) {
             if free_count == 0 { continue };
             free_space_map[free_count as usize].push_back(offset);
@@ -128,8 +134,6 @@ fn solve_part2(
         
     }
 
-    // print_file_map(&file_map);
-
     assert!(free_space_map[0].is_empty());
     free_space_map.iter().for_each(|m| assert!(m.iter().is_sorted()));
 
@@ -139,8 +143,8 @@ fn solve_part2(
             .iter_mut()
             .enumerate()
             .skip(f.file_size)
-            .filter(|(free_count, free_list)| {
-                free_list.front().map_or(false,|offset| *offset < f.offset )
+            .filter(|(_, free_list)| {
+                free_list.front().is_some_and(|offset| *offset < f.offset)
             })
             .min_by_key(|(_, free_list)| *free_list.front().unwrap()) {
 
@@ -165,63 +169,462 @@ fn solve_part2(
         output_map.push(f);
     }
 
-    output_map.sort_by(|e1, e2| e1.offset.cmp(&e2.offset) );
-
-    // print_file_map(&output_map);
+    output_map.sort_by_key(|f| f.offset);
 
     output_map.iter().map(|f| compute_score(f.id, f.offset, f.file_size as u8)).sum()
 
 }   
 
-enum DiskSlot {
-    File(File),
-    Free{offset: usize, count: usize}
+/// A run of blocks belonging to one file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Extent {
+    offset: usize,
+    len: usize,
+    id: usize,
 }
 
-fn solve_part2_bruteforce(data: &[u8]) -> u64 {
+/// How to compact a disk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, strum::Display)]
+#[strum(serialize_all = "kebab-case")]
+enum Policy {
+    /// Move single blocks from the end into the leftmost free block (part 1).
+    Blocks,
+    /// Move each whole file to the leftmost span that fits (part 2).
+    FirstFit,
+    /// Move each whole file to the smallest span that fits.
+    BestFit,
+    /// Move each whole file to the largest span that fits.
+    WorstFit,
+    /// Slide every file left so there's no free space between them.
+    Defragment,
+}
 
-    let mut disk: Vec<DiskSlot> = Vec::new();
+/// Free spans grouped by length, with a min-heap of offsets for each length that has any.
+struct FreeIndex {
+    by_len: BTreeMap<usize, BinaryHeap<Reverse<usize>>>,
+}
 
-    let mut offset = 0usize;
-    let mut id = 0usize;
-    for file_and_free in data.chunks(2) {
-        let file_size = file_and_free[0] as usize;
-        assert!(file_size != 0);
-        disk.push(DiskSlot::File(File{offset, id, file_size})); 
+impl FreeIndex {
+    fn new(disk: &Disk) -> Self {
+        let mut index = FreeIndex { by_len: BTreeMap::new() };
+        for (offset, len) in disk.free_spans() {
+            index.insert(offset, len);
+        }
 
-        // println!("{id}: {file_and_free:?}");
+        index
+    }
 
-        offset += file_size as usize;
-        id += 1;
+    fn insert(&mut self, offset: usize, len: usize) {
+        self.by_len.entry(len).or_default().push(Reverse(offset));
+    }
 
-        if (file_and_free.len() == 2 &&
-            file_and_free[1] != 0) {
+    /// Takes a span for `len` blocks that starts before `before`, putting back whatever is
+    /// left of it.
+    fn take(&mut self, len: usize, before: usize, policy: Policy) -> Option<usize> {
+        let mut candidates = self.by_len
+            .range(len..)
+            .map(|(&free_len, free_list)| (free_len, free_list.peek().unwrap().0))
+            .filter(|&(_, offset)| offset < before);
+
+        let (free_len, offset) = match policy {
+            Policy::FirstFit => candidates.min_by_key(|&(_, offset)| offset),
+            Policy::BestFit => candidates.next(),
+            Policy::WorstFit => candidates.next_back(),
+            Policy::Blocks | Policy::Defragment => unreachable!("{policy} doesn't move whole files"),
+        }?;
+
+        let free_list = self.by_len.get_mut(&free_len).unwrap();
+        free_list.pop();
+        if free_list.is_empty() {
+            self.by_len.remove(&free_len);
+        }
 
-            disk.push(DiskSlot::Free{offset, count: file_and_free[1] as usize});
+        if free_len > len {
+            self.insert(offset + len, free_len - len);
         }
+
+        Some(offset)
     }
+}
 
-    0
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+struct FragmentationStats {
+    /// One past the last used block.
+    end: usize,
+    /// Free spans before `end`.
+    free_spans: usize,
+    free_blocks: usize,
+    largest_free_span: usize,
+    /// Files stored in more than one extent.
+    fragmented_files: usize,
 }
 
-fn main() -> Result<(), Box<dyn Error>> {
-    let file_name = env::args().nth(1).unwrap_or("input_sample.txt".into());
+/// The blocks of a disk as extents sorted by offset. Adjacent extents of the same file are
+/// merged, and anything not covered is free.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Disk {
+    extents: Vec<Extent>,
+}
+
+impl Disk {
+    /// Lays out alternating file and free lengths.
+    fn from_lengths(lengths: impl IntoIterator<Item = usize>) -> Self {
+        let mut extents = Vec::new();
+        let mut offset = 0usize;
+        for (i, len) in lengths.into_iter().enumerate() {
+            if i % 2 == 0 {
+                extents.push(Extent { offset, len, id: i / 2 });
+            }
+            offset += len;
+        }
+
+        Self::from_extents(extents)
+    }
+
+    /// Reads a disk map as it streams in. Lengths are single digits, unless the first
+    /// buffered chunk has a comma, in which case they're separated by commas or whitespace.
+    /// Whitespace is otherwise ignored, so wrapped maps work too.
+    fn read(mut reader: impl BufRead) -> Result<Self, PuzzleError> {
+        let separated = reader.fill_buf()?.contains(&b',');
+
+        let mut lengths = Vec::new();
+        let mut number = None;
+        for (i, byte) in reader.bytes().enumerate() {
+            let byte = byte?;
+            match byte {
+                b'0'..=b'9' if !separated => lengths.push((byte - b'0') as usize),
+                b'0'..=b'9' => {
+                    let n = number.unwrap_or(0usize)
+                        .checked_mul(10)
+                        .and_then(|n| n.checked_add((byte - b'0') as usize))
+                        .ok_or(PuzzleError::LengthOverflow(i))?;
+                    number = Some(n);
+                }
+                b',' if separated => lengths.extend(number.take()),
+                b if b.is_ascii_whitespace() => lengths.extend(number.take()),
+                _ => return Err(PuzzleError::UnexpectedCharacter(byte as char, i)),
+            }
+        }
+
+        lengths.extend(number);
+        Ok(Self::from_lengths(lengths))
+    }
+
+    fn from_extents(mut extents: Vec<Extent>) -> Self {
+        extents.retain(|e| e.len > 0);
+        extents.sort_by_key(|e| e.offset);
+        extents.dedup_by(|next, prev| {
+            let adjacent = prev.id == next.id && prev.offset + prev.len == next.offset;
+            if adjacent {
+                prev.len += next.len;
+            }
+            adjacent
+        });
+
+        Disk { extents }
+    }
+
+    /// (offset, len) of the free spans between extents.
+    fn free_spans(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        self.extents
+            .iter()
+            .scan(0usize, |end, e| {
+                let gap = (*end, e.offset - *end);
+                *end = e.offset + e.len;
+                Some(gap)
+            })
+            .filter(|&(_, len)| len > 0)
+    }
+
+    fn checksum(&self) -> u64 {
+        self.extents.iter().map(|e| {
+            let (id, offset, len) = (e.id as u64, e.offset as u64, e.len as u64);
+            id * (len * offset + len * len.saturating_sub(1) / 2)
+        }).sum()
+    }
+
+    fn stats(&self) -> FragmentationStats {
+        let extents_per_file = self.extents.iter().counts_by(|e| e.id);
+        let spans = self.free_spans().map(|(_, len)| len).collect_vec();
+        FragmentationStats {
+            end: self.extents.last().map_or(0, |e| e.offset + e.len),
+            free_spans: spans.len(),
+            free_blocks: spans.iter().sum(),
+            largest_free_span: spans.iter().copied().max().unwrap_or(0),
+            fragmented_files: extents_per_file.values().filter(|&&n| n > 1).count(),
+        }
+    }
+
+    fn compact(&self, policy: Policy) -> Disk {
+        match policy {
+            Policy::Blocks => self.compact_blocks(),
+            Policy::Defragment => {
+                let mut offset = 0;
+                Self::from_extents(self.extents.iter().map(|&e| {
+                    let moved = Extent { offset, ..e };
+                    offset += e.len;
+                    moved
+                }).collect())
+            }
+            Policy::FirstFit | Policy::BestFit | Policy::WorstFit => {
+                // Space freed by a move is right of every file still to be moved, so it never
+                // needs to go back in the index.
+                let mut free = FreeIndex::new(self);
+                let mut extents = self.extents.clone();
+                for e in extents.iter_mut().sorted_by_key(|e| Reverse(e.id)) {
+                    if let Some(offset) = free.take(e.len, e.offset, policy) {
+                        e.offset = offset;
+                    }
+                }
+
+                Self::from_extents(extents)
+            }
+        }
+    }
+
+    /// Fills each gap from the back until everything is packed at the front.
+    fn compact_blocks(&self) -> Disk {
+        let mut pending: VecDeque<Extent> = self.extents.iter().copied().collect();
+        let mut extents = Vec::new();
+        let mut offset = 0usize;
+        while let Some(&front) = pending.front() {
+            if front.offset == offset || pending.len() == 1 {
+                extents.push(Extent { offset, ..front });
+                offset += front.len;
+                pending.pop_front();
+                continue;
+            }
+
+            let back = pending.back_mut().unwrap();
+            let len = min(front.offset - offset, back.len);
+            extents.push(Extent { offset, len, id: back.id });
+            offset += len;
+            back.len -= len;
+            if back.len == 0 {
+                pending.pop_back();
+            }
+        }
+
+        Self::from_extents(extents)
+    }
+}
+
+/// How to draw a disk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Render {
+    /// Every block is its file id padded to the width of the largest id.
+    Fixed,
+    /// Every block is the last digit of its id on a background color picked by the id.
+    Color,
+}
+
+/// Draws the disk up to its last used block, `per_line` blocks to a line, each line starting
+/// with the offset of its first block.
+fn render_disk(disk: &Disk, render: Render, per_line: usize) -> String {
+    let end = disk.stats().end;
+    let mut blocks = vec![None; end];
+    for e in &disk.extents {
+        blocks[e.offset..e.offset + e.len].fill(Some(e.id));
+    }
+
+    let digits = |n: usize| n.checked_ilog10().unwrap_or(0) as usize + 1;
+    let id_width = digits(disk.extents.iter().map(|e| e.id).max().unwrap_or(0));
+    let offset_width = digits(end);
+
+    let mut out = String::new();
+    for (line, chunk) in blocks.chunks(per_line.max(1)).enumerate() {
+        write!(out, "{:>offset_width$}:", line * per_line.max(1)).unwrap();
+        for block in chunk {
+            match (render, block) {
+                (Render::Fixed, Some(id)) => write!(out, " {id:>id_width$}").unwrap(),
+                (Render::Fixed, None) => write!(out, " {:>id_width$}", ".").unwrap(),
+                (Render::Color, Some(id)) => write!(out, "\x1b[30;48;5;{}m{}\x1b[0m", 16 + id * 37 % 216, id % 10).unwrap(),
+                (Render::Color, None) => out.push('.'),
+            }
+        }
+        out.push('\n');
+    }
 
-    let str = fs::read_to_string(file_name)?;
-    let data: Vec<u8> = str
-        .trim_ascii()
-        .as_bytes()
-        .iter()
-        .map(|c| *c - b'0')
-        .collect();
+    out
+}
+
+#[derive(Parser, Debug)]
+struct Args {
+    /// Disk map to read, or - for stdin.
+    #[arg(default_value = "input_sample.txt")]
+    input_file: String,
+
+    /// Compact with these policies and print the checksum and fragmentation of each.
+    #[arg(short, long, value_enum, value_delimiter = ',')]
+    policy: Vec<Policy>,
+
+    /// Compare all the policies.
+    #[arg(short, long, default_value_t = false)]
+    compare: bool,
 
-    let files = data.iter().step_by(2).enumerate();
-    let frees = data.iter().skip(1).step_by(2);
+    /// Draw each compacted disk.
+    #[arg(short, long, value_enum, num_args = 0..=1, default_missing_value = "fixed")]
+    show: Option<Render>,
+
+    /// Blocks per line when drawing.
+    #[arg(long, default_value_t = 64)]
+    blocks_per_line: usize,
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let args = Args::parse();
 
-    let part1 = time_it("part1", || solve_part1(files.clone(), frees.clone()));
+    let disk = if args.input_file == "-" {
+        Disk::read(io::stdin().lock())?
+    } else {
+        Disk::read(io::BufReader::new(fs::File::open(&args.input_file)?))?
+    };
+
+    let part1 = time_it("part1", || disk.compact(Policy::Blocks).checksum());
     dbg!(part1);
 
-    let part2 = time_it("part2", || solve_part2(&data));
+    let part2 = time_it("part2", || disk.compact(Policy::FirstFit).checksum());
     dbg!(part2);
+
+    let policies = if args.compare { Policy::value_variants().to_vec() } else { args.policy };
+    if !policies.is_empty() {
+        println!("{:<12}{:>16}{:>10}{:>12}{:>12}{:>14}{:>12}", "policy", "checksum", "end", "free spans", "free blocks", "largest free", "fragmented");
+        for policy in policies {
+            let compacted = disk.compact(policy);
+            let stats = compacted.stats();
+            println!("{:<12}{:>16}{:>10}{:>12}{:>12}{:>14}{:>12}",
+                policy.to_string(), compacted.checksum(), stats.end, stats.free_spans,
+                stats.free_blocks, stats.largest_free_span, stats.fragmented_files);
+
+            if let Some(render) = args.show {
+                print!("{}", render_disk(&compacted, render, args.blocks_per_line));
+            }
+        }
+    }
+
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read_disk(map: &str) -> Disk {
+        Disk::read(map.as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn sample() {
+        let disk = read_disk(include_str!("../input_sample.txt"));
+        assert_eq!(disk.compact(Policy::Blocks).checksum(), 1928);
+        assert_eq!(disk.compact(Policy::FirstFit).checksum(), 2858);
+
+        let defragmented = disk.compact(Policy::Defragment);
+        assert_eq!(defragmented.stats(), FragmentationStats { end: 28, ..Default::default() });
+
+        let blocks = disk.compact(Policy::Blocks).stats();
+        assert_eq!((blocks.end, blocks.free_spans), (28, 0));
+        assert!(blocks.fragmented_files > 0);
+
+        // 00992111777.44.333....5555.6666.....8888..
+        let first_fit = disk.compact(Policy::FirstFit).stats();
+        assert_eq!((first_fit.end, first_fit.free_spans, first_fit.free_blocks, first_fit.largest_free_span), (40, 5, 12, 5));
+    }
+
+    #[test]
+    fn policies_pick_spans() {
+        // 0..1....22
+        let disk = read_disk("12142");
+        let file2 = |d: &Disk| d.extents.iter().find(|e| e.id == 2).unwrap().offset;
+        assert_eq!(file2(&disk.compact(Policy::FirstFit)), 1);
+        assert_eq!(file2(&disk.compact(Policy::BestFit)), 1);
+        assert_eq!(file2(&disk.compact(Policy::WorstFit)), 4);
+
+        // 0....1..2
+        let disk = read_disk("14121");
+        assert_eq!(file2(&disk.compact(Policy::FirstFit)), 1);
+        assert_eq!(file2(&disk.compact(Policy::BestFit)), 6);
+        assert_eq!(file2(&disk.compact(Policy::WorstFit)), 1);
+    }
+
+    #[test]
+    fn disk_matches_solvers() {
+        let mut state = 0x2545_f491_4f6c_dd1du64;
+        for _ in 0..200 {
+            let data = (0..31).map(|i| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                let d = (state % 10) as u8;
+                if i % 2 == 0 { d.max(1) } else { d }
+            }).collect_vec();
+
+            let disk = Disk::from_lengths(data.iter().map(|&d| d as usize));
+            let files = data.iter().step_by(2).enumerate();
+            let frees = data.iter().skip(1).step_by(2);
+            assert_eq!(disk.compact(Policy::Blocks).checksum(), solve_part1(files, frees), "{data:?}");
+            assert_eq!(disk.compact(Policy::FirstFit).checksum(), solve_part2(&data), "{data:?}");
+        }
+    }
+
+    #[test]
+    fn long_lengths() {
+        assert_eq!(read_disk("2,3,3,3,1,3,3,1,2,1,4,1,4,1,3,1,4,0,2"), read_disk("2333133121414131402\n"));
+        assert_eq!(read_disk("1, 12\n3"), Disk::from_lengths([1, 12, 3]));
+        assert!(matches!(Disk::read("12a".as_bytes()), Err(PuzzleError::UnexpectedCharacter('a', 2))));
+
+        // A 12-block gap takes the 10-block file.
+        let compacted = read_disk("1,12,1,0,10").compact(Policy::FirstFit);
+        assert_eq!(compacted.extents[1], Extent { offset: 1, len: 10, id: 2 });
+    }
+
+    /// Moves each file by looking at every free span, for checking the index against.
+    fn compact_naive(disk: &Disk, policy: Policy) -> Disk {
+        let mut disk = disk.clone();
+        for id in (0..disk.extents.len()).rev() {
+            let i = disk.extents.iter().position(|e| e.id == id).unwrap();
+            let file = disk.extents[i];
+            let fits = disk.free_spans().filter(|&(offset, len)| offset < file.offset && len >= file.len);
+            let span = match policy {
+                Policy::FirstFit => fits.min_by_key(|&(offset, _)| offset),
+                Policy::BestFit => fits.min_by_key(|&(offset, len)| (len, offset)),
+                Policy::WorstFit => fits.min_by_key(|&(offset, len)| (Reverse(len), offset)),
+                _ => unreachable!(),
+            };
+
+            if let Some((offset, _)) = span {
+                disk.extents[i].offset = offset;
+                disk = Disk::from_extents(disk.extents);
+            }
+        }
+
+        disk
+    }
+
+    #[test]
+    fn free_index_matches_naive() {
+        let mut state = 0x9e37_79b9_7f4a_7c15u64;
+        for _ in 0..100 {
+            let lengths = (0..41).map(|i| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                let len = (state % 25) as usize;
+                if i % 2 == 0 { len.max(1) } else { len }
+            }).collect_vec();
+
+            let disk = Disk::from_lengths(lengths.iter().copied());
+            for policy in [Policy::FirstFit, Policy::BestFit, Policy::WorstFit] {
+                assert_eq!(disk.compact(policy), compact_naive(&disk, policy), "{policy} {lengths:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn renders_fixed_width() {
+        let disk = read_disk("1,2,12");
+        assert_eq!(render_disk(&disk, Render::Fixed, 8), concat!(
+            " 0: 0 . . 1 1 1 1 1\n",
+            " 8: 1 1 1 1 1 1 1\n"));
+    }
+}
@@ -1,28 +1,38 @@
-use std::cmp::min;
-use std::collections::BTreeMap;
-use std::collections::VecDeque;
+use std::cmp::Reverse;
 use std::error::Error;
-use std::env;
 use std::fs;
-use std::ops::Index;
-use std::path;
-use std::slice::SliceIndex;
-use std::thread::current;
 use std::time::Instant;
 use bit_set::BitSet;
+use clap::Parser;
 use itertools::Itertools;
+use thiserror::Error;
 
 fn time_it<T>(name: &str, f: impl FnOnce() -> T) -> T {
     let start = Instant::now();
 
     let ret = f();
-    
+
     let elapsed = start.elapsed();
     println!("{name} took: {elapsed:?}");
-    
+
     ret
 }
 
+#[derive(Debug, Clone, Error, PartialEq)]
+enum PuzzleError {
+    #[error("bad height '{1}' on line {0}")]
+    BadHeight(usize, String),
+
+    #[error("line {0} is a different length from the first")]
+    RaggedRow(usize),
+
+    #[error("empty map")]
+    Empty,
+
+    #[error("{0},{1} is not a trailhead")]
+    NotATrailhead(usize, usize),
+}
+
 const DIRECTION: [[isize;2]; 4] = [
     [-1isize, 0isize],
     [0isize, -1isize],
@@ -30,10 +40,15 @@ const DIRECTION: [[isize;2]; 4] = [
     [1isize, 0isize]
 ];
 
+/// Height of cells that can't be stepped on.
+const IMPASSABLE: u32 = u32::MAX;
+
 struct Puzzle {
-    map: Vec<Vec<u8>>,
+    map: Vec<Vec<u32>>,
     rows: usize,
-    cols: usize
+    cols: usize,
+    /// Height of the summits that trails end on.
+    max_height: u32,
 }
 
 #[derive(Debug, Default)]
@@ -48,12 +63,12 @@ impl Iterator for DirectionIterator {
     type Item = [usize;2];
 
     fn next(&mut self) -> Option<Self::Item> {
-        while (self.current_dir < DIRECTION.len()) {
+        while self.current_dir < DIRECTION.len() {
             let d = DIRECTION[self.current_dir];
             self.current_dir += 1;
 
             let new_loc: [Option<usize>;2] = std::array::from_fn(|a| self.location[a].checked_add_signed(d[a]));
-                    
+
             if let [Some(r), Some(c)] = new_loc {
                 if (r < self.rows) && (c < self.cols) {
                     return Some([r, c]);
@@ -72,147 +87,250 @@ impl Puzzle {
 
     fn cell_index(&self, location: [usize; 2]) -> usize {
         assert!(location[0] < self.rows);
-        assert!(location[1] < self.cols); 
+        assert!(location[1] < self.cols);
         location[0] * self.cols + location[1]
     }
 
-    fn value_at(&self, location: [usize;2]) -> u8 {
+    fn value_at(&self, location: [usize;2]) -> u32 {
         self.map[location[0]][location[1]]
     }
+
+    /// The neighbors one step higher than `location`.
+    fn steps_up(&self, location: [usize;2]) -> impl Iterator<Item = [usize;2]> + '_ {
+        let next = self.value_at(location).checked_add(1);
+        self.directions_at(location).filter(move |&l| Some(self.value_at(l)) == next)
+    }
+
+    fn trailheads(&self) -> impl Iterator<Item = [usize;2]> + '_ {
+        (0..self.rows)
+            .cartesian_product(0..self.cols)
+            .map(|(r, c)| [r, c])
+            .filter(|&l| self.value_at(l) == 0)
+    }
 }
 
-fn compute_score(
-    puzzle: &Puzzle,
-    score_map: &[Vec<usize>]
-    ) -> usize
-{
-    let mut score = 0;
-    for r in 0..puzzle.rows {
-        for c in 0..puzzle.cols {
-            let loc = [r,c];
-            
-            if puzzle.value_at(loc) == 0 {
-                score += score_map[loc[0]][loc[1]];
+/// Rows are either one height per character, 0-9 then a-z for 10-35, or numbers separated by
+/// commas or spaces. Anything that isn't a height, like '.', is impassable.
+fn parse_puzzle(text: &str, max_height: u32) -> Result<Puzzle, PuzzleError> {
+    let map: Vec<Vec<u32>> = text
+        .trim_ascii()
+        .lines()
+        .enumerate()
+        .map(|(i, l)| {
+            if l.contains([',', ' ']) {
+                l.split([',', ' '])
+                    .filter(|h| !h.is_empty())
+                    .map(|h| match h {
+                        "." => Ok(IMPASSABLE),
+                        _ => h.parse().map_err(|_| PuzzleError::BadHeight(i + 1, h.to_string())),
+                    })
+                    .collect()
+            } else {
+                Ok(l.chars().map(|c| c.to_digit(36).unwrap_or(IMPASSABLE)).collect())
             }
-        }
+        })
+        .try_collect()?;
+
+    let rows = map.len();
+    let cols = map.first().map(|r| r.len()).ok_or(PuzzleError::Empty)?;
+    if let Some(i) = map.iter().position(|r| r.len() != cols) {
+        return Err(PuzzleError::RaggedRow(i + 1));
     }
 
-    score
+    Ok(Puzzle{map, rows, cols, max_height})
 }
 
-fn solve_part1_recurse (
-    puzzle: &Puzzle,
-    location: [usize; 2],
-    visited: &mut BitSet,
-    reachable_count: &mut [Vec<usize>]
-    )
-{
-
-    let val = puzzle.value_at(location);
-    if val == 0 {
-        return;
+/// For every cell, the summits reachable by climbing one step at a time and the number of
+/// distinct trails that get there.
+struct TrailMap {
+    summits: Vec<[usize;2]>,
+    /// Indexed by cell, with bits indexing `summits`.
+    reachable: Vec<BitSet>,
+    trails: Vec<u128>,
+}
+
+/// Each step goes up by one, so the trails form a DAG. Visiting cells from the top down means
+/// every cell's higher neighbors are done before it is. Trail counts saturate.
+fn build_trail_map(puzzle: &Puzzle) -> TrailMap {
+    let cells = puzzle.rows * puzzle.cols;
+    let mut summits = Vec::new();
+    let mut reachable = vec![BitSet::new(); cells];
+    let mut trails = vec![0u128; cells];
+
+    let by_height = (0..puzzle.rows)
+        .cartesian_product(0..puzzle.cols)
+        .map(|(r, c)| [r, c])
+        .filter(|&l| puzzle.value_at(l) <= puzzle.max_height)
+        .sorted_by_key(|&l| Reverse(puzzle.value_at(l)));
+
+    for loc in by_height {
+        let i = puzzle.cell_index(loc);
+        if puzzle.value_at(loc) == puzzle.max_height {
+            reachable[i].insert(summits.len());
+            trails[i] = 1;
+            summits.push(loc);
+            continue;
+        }
+
+        let mut cell_reachable = BitSet::new();
+        let mut cell_trails = 0u128;
+        for up in puzzle.steps_up(loc) {
+            let u = puzzle.cell_index(up);
+            cell_reachable.union_with(&reachable[u]);
+            cell_trails = cell_trails.saturating_add(trails[u]);
+        }
+
+        reachable[i] = cell_reachable;
+        trails[i] = cell_trails;
     }
 
-    puzzle.directions_at(location)
-        .for_each(|l| {
-            let loc_val = puzzle.value_at(l);
-            if loc_val + 1 == val {
-                if visited.insert(puzzle.cell_index(l)) {
-                    reachable_count[l[0]][l[1]] += 1;
-                    solve_part1_recurse(puzzle, l, visited, reachable_count);
-                }
-            }
-        });
+    TrailMap { summits, reachable, trails }
 }
 
 fn solve_part1(
-    puzzle: &Puzzle
+    puzzle: &Puzzle,
+    trail_map: &TrailMap
 ) -> usize {
-    let mut reachable_count: Vec<Vec<usize>> = vec![vec![0;puzzle.cols];puzzle.rows];
-    let mut visited = BitSet::with_capacity(puzzle.rows * puzzle.cols);
-    for r in 0..puzzle.rows {
-        for c in 0..puzzle.cols {
-            let loc = [r,c];
-            if puzzle.map[loc[0]][loc[1]] == 9 {
-                visited.clear();
-                solve_part1_recurse(puzzle, loc, &mut visited, &mut reachable_count);
-            }
-        }
-    }
-
-    compute_score(puzzle, &reachable_count)
+    puzzle.trailheads()
+        .map(|l| trail_map.reachable[puzzle.cell_index(l)].len())
+        .sum()
 }
 
 fn solve_part2(
-    puzzle: &Puzzle
-) -> usize {
-    let mut bfs_queue: VecDeque<[usize;2]> = VecDeque::new();
-    let mut path_count = vec![vec![0usize; puzzle.cols]; puzzle.rows];
-    let mut visited = BitSet::with_capacity(puzzle.rows * puzzle.cols);
-
-    // seed the bfs queue with all the '9's.
-    for r in 0..puzzle.rows {
-        for c in 0..puzzle.cols {
-            if (puzzle.value_at([r,c]) == 9) {
-                bfs_queue.push_back([r,c]);
-                path_count[r][c] = 1;
-            }
-        }
-    }
+    puzzle: &Puzzle,
+    trail_map: &TrailMap
+) -> u128 {
+    puzzle.trailheads()
+        .map(|l| trail_map.trails[puzzle.cell_index(l)])
+        .fold(0, u128::saturating_add)
+}
 
-    while let Some(location) = bfs_queue.pop_front() {
-        let val = puzzle.map[location[0]][location[1]];
-        let cur_path_count = path_count[location[0]][location[1]];
-        puzzle.directions_at(location)
-            .for_each(|l| {
-                let loc_val = puzzle.value_at(l);
-                if loc_val + 1 == val {
-                    path_count[l[0]][l[1]] += cur_path_count;
-                    if visited.insert(puzzle.cell_index(l)) {
-                        bfs_queue.push_back(l);
-                    }
-                }
-            });
+/// Up to `limit` trails from `start` to a summit. Only steps onto cells that still have a
+/// trail to finish, so no time is spent on dead ends.
+fn list_trails(
+    puzzle: &Puzzle,
+    trail_map: &TrailMap,
+    start: [usize;2],
+    limit: usize
+) -> Vec<Vec<[usize;2]>> {
+    fn recurse(
+        puzzle: &Puzzle,
+        trail_map: &TrailMap,
+        trail: &mut Vec<[usize;2]>,
+        limit: usize,
+        found: &mut Vec<Vec<[usize;2]>>
+    ) {
+        let loc = *trail.last().unwrap();
+        if puzzle.value_at(loc) == puzzle.max_height {
+            found.push(trail.clone());
+            return;
+        }
 
-        /*
-        println!("location: {location:?}");
-        for r in 0..puzzle.rows {
-            for c in 0..puzzle.cols {
-                print!("{},", path_count[r][c]);
+        for up in puzzle.steps_up(loc) {
+            if found.len() == limit {
+                return;
             }
 
-            println!();
+            if trail_map.trails[puzzle.cell_index(up)] > 0 {
+                trail.push(up);
+                recurse(puzzle, trail_map, trail, limit, found);
+                trail.pop();
+            }
         }
-        */
     }
 
-    compute_score(puzzle, &path_count)
+    let mut found = Vec::new();
+    if limit > 0 && trail_map.trails[puzzle.cell_index(start)] > 0 {
+        recurse(puzzle, trail_map, &mut vec![start], limit, &mut found);
+    }
+
+    found
 }
 
-fn main() -> Result<(), Box<dyn Error>> {
-    let file_name = env::args().nth(1).unwrap_or("input_sample.txt".into());
+fn parse_location(s: &str) -> Result<[usize;2], String> {
+    let (r, c) = s.split_once(',').ok_or("expected row,col")?;
+    let parse = |v: &str| v.trim().parse::<usize>().map_err(|e| e.to_string());
+    Ok([parse(r)?, parse(c)?])
+}
 
-    let str = fs::read_to_string(file_name)?;
-    let map: Vec<Vec<u8>> = str
-        .trim_ascii()
-        .lines()
-        .map(|l| l.chars().map(|c| 
-            match c {
-                '0'..='9' => c as u8 - '0' as u8,
-                _ => 10u8
-            }).collect())
-        .collect();
+#[derive(Parser, Debug)]
+struct Args {
+    #[arg(default_value = "input_sample.txt")]
+    input_file: String,
 
-    let rows = map.len();
-    let cols = map[0].len();
-    assert!(map.iter().all(|r| r.len() == cols));
+    /// Height of the summits.
+    #[arg(short, long, default_value_t = 9)]
+    max_height: u32,
+
+    /// List the trails from the trailhead at row,col.
+    #[arg(short, long, value_parser = parse_location)]
+    trailhead: Option<[usize;2]>,
 
-    let puzzle = Puzzle{map, rows, cols};
+    /// Most trails to list.
+    #[arg(short, long, default_value_t = 20)]
+    limit: usize,
+}
 
-    let part1 = time_it("part1", || solve_part1(&puzzle));
+fn main() -> Result<(), Box<dyn Error>> {
+    let args = Args::parse();
+
+    let str = fs::read_to_string(&args.input_file)?;
+    let puzzle = parse_puzzle(&str, args.max_height)?;
+
+    let trail_map = time_it("trail map", || build_trail_map(&puzzle));
+
+    let part1 = time_it("part1", || solve_part1(&puzzle, &trail_map));
     dbg!(part1);
 
-    let part2 = time_it("part2", || solve_part2(&puzzle));
+    let part2 = time_it("part2", || solve_part2(&puzzle, &trail_map));
     dbg!(part2);
+
+    if let Some(start @ [r, c]) = args.trailhead {
+        if r >= puzzle.rows || c >= puzzle.cols || puzzle.value_at(start) != 0 {
+            Err(PuzzleError::NotATrailhead(r, c))?
+        }
+
+        let i = puzzle.cell_index(start);
+        let summits = trail_map.reachable[i].iter().map(|s| trail_map.summits[s]).collect_vec();
+        println!("trailhead {r},{c}: {} trails to summits {summits:?}", trail_map.trails[i]);
+        for trail in list_trails(&puzzle, &trail_map, start, args.limit) {
+            println!("{}", trail.iter().map(|[r, c]| format!("{r},{c}")).join(" -> "));
+        }
+    }
+
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sample() {
+        let puzzle = parse_puzzle(include_str!("../input_sample.txt"), 9).unwrap();
+        let trail_map = build_trail_map(&puzzle);
+        assert_eq!(solve_part1(&puzzle, &trail_map), 36);
+        assert_eq!(solve_part2(&puzzle, &trail_map), 81);
+
+        let puzzle = parse_puzzle(include_str!("../input_sample2.txt"), 9).unwrap();
+        let trail_map = build_trail_map(&puzzle);
+        assert_eq!((solve_part1(&puzzle, &trail_map), solve_part2(&puzzle, &trail_map)), (1, 3));
+
+        let trails = list_trails(&puzzle, &trail_map, [0, 5], 20);
+        assert_eq!(trails.len(), 3);
+        assert!(trails.iter().all(|t| t.len() == 10 && t.iter().map(|&l| puzzle.value_at(l)).eq(0..10)));
+        assert!(trails.iter().all_unique());
+        assert_eq!(list_trails(&puzzle, &trail_map, [0, 5], 2).len(), 2);
+    }
+
+    #[test]
+    fn taller_maps() {
+        let puzzle = parse_puzzle("0123456789abc\n.....d.......", 12).unwrap();
+        let trail_map = build_trail_map(&puzzle);
+        assert_eq!((solve_part1(&puzzle, &trail_map), solve_part2(&puzzle, &trail_map)), (1, 1));
+
+        let puzzle = parse_puzzle("0, 1, 2\n1, 2, 3\n2, 3, .", 3).unwrap();
+        let trail_map = build_trail_map(&puzzle);
+        assert_eq!((solve_part1(&puzzle, &trail_map), solve_part2(&puzzle, &trail_map)), (2, 6));
+    }
+}
@@ -1,128 +1,541 @@
-use core::num;
-use std::cmp::min;
-use std::collections::BTreeMap;
 use std::collections::HashMap;
 use std::collections::HashSet;
 use std::collections::VecDeque;
 use std::error::Error;
-use std::env;
+use std::fmt;
+use std::fmt::Write as _;
 use std::fs;
-use std::ops::Index;
-use std::path;
-use std::slice::SliceIndex;
-use std::thread::current;
+use std::path::Path;
+use std::path::PathBuf;
+use std::str::FromStr;
 use std::time::Instant;
-use bit_set::BitSet;
-use itertools::Itertools;
 use arrayvec::ArrayVec;
+use clap::Parser;
+use itertools::Itertools;
+use thiserror::Error;
+
+#[derive(Debug, Clone, Error, PartialEq)]
+enum PuzzleError {
+    #[error("can't parse rule '{0}'")]
+    BadRule(String),
+
+    #[error("stone count overflowed, try --modulus")]
+    Overflow,
+
+    #[error("more than {0} distinct values are reachable, so the matrix path can't be used")]
+    NotClosed(usize),
+}
 
 fn time_it<T>(name: &str, f: impl FnOnce() -> T) -> T {
     let start = Instant::now();
 
     let ret = f();
-    
+
     let elapsed = start.elapsed();
     println!("{name} took: {elapsed:?}");
-    
+
     ret
 }
 struct Puzzle {
     stones: Vec<u64>
 }
 
+fn digit_count(x: u64, base: u64) -> u32 {
+    if x == 0 { 1 } else { x.ilog(base) + 1 }
+}
+
+/// Splits the digits of `x` into a high and low half. The high half keeps the middle digit
+/// when the count is odd.
+fn split_digits(x: u64, base: u64) -> [u64; 2] {
+    let half = base.pow(digit_count(x, base) / 2);
+    [x / half, x % half]
+}
+
+#[test]
+fn test_split_digits() {
+    assert_eq!(split_digits(999, 10), [99, 9]);
+    assert_eq!(split_digits(9999, 10), [99, 99]);
+    assert_eq!(split_digits(9009, 10), [90, 9]);
+    assert_eq!(split_digits(10001, 10), [100, 1]);
+    assert_eq!(split_digits(0b1101, 2), [0b11, 0b01]);
+}
+
+/// Which stones a rule applies to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Predicate {
+    Any,
+    Equals(u64),
+    EvenDigits,
+    OddDigits,
+    Divisible(u64),
+}
+
+/// What a matching rule turns a stone into.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Transform {
+    Set(u64),
+    Split,
+    Multiply(u64),
+    Add(u64),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Rule {
+    when: Predicate,
+    then: Transform,
+}
 
-impl Puzzle {
+/// An ordered list of rules where the first match decides what happens to a stone. Stones
+/// that match no rule are left as they are. Digits are counted and split in `base`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Rules {
+    rules: Vec<Rule>,
+    base: u64,
 }
 
-fn try_split(x: u64) -> Option<[u64;2]> {
-    let number_of_digits = x.ilog10() + 1;
-    if (number_of_digits % 2 == 0) {
-        let half = 10u64.pow(number_of_digits / 2);
-        Some([x / half, x % half])
-    } else {
-        None
+impl Rules {
+    /// The puzzle's rules: 0 becomes 1, even-digit stones split, everything else is multiplied.
+    fn standard(multiplier: u64, base: u64) -> Self {
+        Rules {
+            rules: vec![
+                Rule { when: Predicate::Equals(0), then: Transform::Set(1) },
+                Rule { when: Predicate::EvenDigits, then: Transform::Split },
+                Rule { when: Predicate::Any, then: Transform::Multiply(multiplier) },
+            ],
+            base,
+        }
+    }
+
+    /// Parses rules written like `=0 -> set 1; even-digits -> split; any -> mul 2024`.
+    /// A bare `mul` uses `multiplier`.
+    fn parse(text: &str, multiplier: u64, base: u64) -> Result<Self, PuzzleError> {
+        let rules = text.split(';')
+            .map(str::trim)
+            .filter(|r| !r.is_empty())
+            .map(|r| {
+                let bad_rule = || PuzzleError::BadRule(r.to_string());
+                let number = |n: &str| u64::from_str(n.trim()).map_err(|_| bad_rule());
+
+                let (when, then) = r.split_once("->").ok_or_else(bad_rule)?;
+                let when = match when.trim() {
+                    "any" => Predicate::Any,
+                    "even-digits" => Predicate::EvenDigits,
+                    "odd-digits" => Predicate::OddDigits,
+                    w if w.starts_with('=') => Predicate::Equals(number(&w[1..])?),
+                    w if w.starts_with("div ") => Predicate::Divisible(number(&w[4..]).and_then(|d| if d == 0 { Err(bad_rule()) } else { Ok(d) })?),
+                    _ => return Err(bad_rule()),
+                };
+
+                let then = match then.trim() {
+                    "split" => Transform::Split,
+                    "mul" => Transform::Multiply(multiplier),
+                    t if t.starts_with("mul ") => Transform::Multiply(number(&t[4..])?),
+                    t if t.starts_with("set ") => Transform::Set(number(&t[4..])?),
+                    t if t.starts_with("add ") => Transform::Add(number(&t[4..])?),
+                    _ => return Err(bad_rule()),
+                };
+
+                Ok(Rule { when, then })
+            })
+            .try_collect()?;
+
+        Ok(Rules { rules, base })
+    }
+
+    fn matches(&self, when: Predicate, stone: u64) -> bool {
+        match when {
+            Predicate::Any => true,
+            Predicate::Equals(n) => stone == n,
+            Predicate::EvenDigits => digit_count(stone, self.base).is_multiple_of(2),
+            Predicate::OddDigits => digit_count(stone, self.base) % 2 == 1,
+            Predicate::Divisible(d) => stone.is_multiple_of(d),
+        }
+    }
+
+    /// The stones that `stone` turns into after one blink.
+    fn apply(&self, stone: u64) -> Result<ArrayVec<u64, 2>, PuzzleError> {
+        let mut out = ArrayVec::new();
+        match self.rules.iter().find(|r| self.matches(r.when, stone)).map(|r| r.then) {
+            None => out.push(stone),
+            Some(Transform::Set(n)) => out.push(n),
+            Some(Transform::Split) => out.extend(split_digits(stone, self.base)),
+            Some(Transform::Multiply(m)) => out.push(stone.checked_mul(m).ok_or(PuzzleError::Overflow)?),
+            Some(Transform::Add(a)) => out.push(stone.checked_add(a).ok_or(PuzzleError::Overflow)?),
+        }
+
+        Ok(out)
     }
 }
 
-#[test]
-fn test_try_split() {
-    assert_eq!(try_split(999), None);
-    assert_eq!(try_split(9999), Some([99, 99]));
-    assert_eq!(try_split(9009), Some([90, 09]));
-    assert_eq!(try_split(10001), None);
+impl fmt::Display for Rules {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let rules = self.rules.iter().map(|r| {
+            let when = match r.when {
+                Predicate::Any => "any".to_string(),
+                Predicate::Equals(n) => format!("={n}"),
+                Predicate::EvenDigits => "even-digits".to_string(),
+                Predicate::OddDigits => "odd-digits".to_string(),
+                Predicate::Divisible(d) => format!("div {d}"),
+            };
+            let then = match r.then {
+                Transform::Set(n) => format!("set {n}"),
+                Transform::Split => "split".to_string(),
+                Transform::Multiply(m) => format!("mul {m}"),
+                Transform::Add(a) => format!("add {a}"),
+            };
+            format!("{when} -> {then}")
+        });
+
+        write!(f, "{} (base {})", rules.format("; "), self.base)
+    }
+}
+
+/// Stone counts, either exact or reduced by a modulus so that huge blink counts stay
+/// representable.
+#[derive(Debug, Clone, Copy)]
+struct Counter {
+    modulus: Option<u64>,
 }
 
-fn stone_rule(stone: u64) -> (u64, Option<u64>)
-{
-    if stone == 0 {
-        (1, None)
-    } else if let Some(split) = try_split(stone) {
-        (split[0], Some(split[1]))
-    } else {
-        (stone * 2024, None)
+impl Counter {
+    fn add(self, a: u64, b: u64) -> Result<u64, PuzzleError> {
+        match self.modulus {
+            Some(m) => Ok(((a as u128 + b as u128) % m as u128) as u64),
+            None => a.checked_add(b).ok_or(PuzzleError::Overflow),
+        }
+    }
+
+    /// Brings a wide accumulator back into range.
+    fn reduce(self, a: u128) -> Result<u64, PuzzleError> {
+        match self.modulus {
+            Some(m) => Ok((a % m as u128) as u64),
+            None => u64::try_from(a).map_err(|_| PuzzleError::Overflow),
+        }
     }
 
+    fn sum(self, values: impl IntoIterator<Item = u64>) -> Result<u64, PuzzleError> {
+        values.into_iter().try_fold(0, |acc, v| self.add(acc, v))
+    }
 }
 
 fn solve_part1(
     stones: &[u64],
+    rules: &Rules,
     blink_count: usize,
     debug: bool
-) -> usize {
-    
+) -> Result<usize, PuzzleError> {
+
     let mut cur_stones = stones.to_vec();
-    if (debug) {
-        println!("{cur_stones:?}"); 
+    if debug {
+        println!("{cur_stones:?}");
     }
 
     for _ in 0..blink_count {
         let mut stone_buffer = Vec::with_capacity(cur_stones.len() * 2);
 
         for stone in cur_stones {
-            let (s1, s2) = stone_rule(stone);
-            stone_buffer.push(s1);
-            if s2.is_some() {
-                stone_buffer.push(s2.unwrap());
-            }
+            stone_buffer.extend(rules.apply(stone)?);
         }
 
         cur_stones = stone_buffer;
-        if (debug) {
-            println!("{cur_stones:?}"); 
+        if debug {
+            println!("{cur_stones:?}");
         }
     }
 
-    cur_stones.len()
+    Ok(cur_stones.len())
+}
+
+type StoneCounts = HashMap<u64, u64>;
+
+fn initial_counts(stones: &[u64], counter: Counter) -> Result<StoneCounts, PuzzleError> {
+    let mut number_to_count = StoneCounts::new();
+    for &s in stones {
+        let count = number_to_count.entry(s).or_default();
+        *count = counter.add(*count, 1)?;
+    }
+
+    Ok(number_to_count)
+}
+
+fn blink_counts(number_to_count: StoneCounts, rules: &Rules, counter: Counter) -> Result<StoneCounts, PuzzleError> {
+    let mut new_number_to_count = StoneCounts::new();
+    for (key, count) in number_to_count {
+        for new_stone in rules.apply(key)? {
+            let new_count = new_number_to_count.entry(new_stone).or_default();
+            *new_count = counter.add(*new_count, count)?;
+        }
+    }
+
+    Ok(new_number_to_count)
+}
+
+/// Blinks a map of stone value to count, which stays small because the same values keep
+/// coming back.
+fn count_stones(
+    stones: &[u64],
+    rules: &Rules,
+    blinks: u64,
+    counter: Counter,
+) -> Result<u64, PuzzleError> {
+    let mut number_to_count = initial_counts(stones, counter)?;
+    for _blink in 0..blinks {
+        number_to_count = blink_counts(number_to_count, rules, counter)?;
+    }
+
+    counter.sum(number_to_count.into_values())
 }
 
 fn solve_part2(
     puzzle: &Puzzle,
-    blinks: usize
-) -> u64 {
-    let mut number_to_count: HashMap<u64, u64> = HashMap::new();
+    rules: &Rules,
+    blinks: u64
+) -> Result<u64, PuzzleError> {
+    count_stones(&puzzle.stones, rules, blinks, Counter { modulus: None })
+}
 
-    puzzle.stones.iter().for_each(|s| *number_to_count.entry(*s).or_default() += 1);
-    
-    for _blink in 0..blinks {
-        let mut new_number_to_count = HashMap::new();
-        for (key, count) in number_to_count {
-            let new_stones = stone_rule(key);
-            *new_number_to_count.entry(new_stones.0).or_default() += count;
-            if let Some(second) = new_stones.1 {
-                *new_number_to_count.entry(second).or_default() += count;
+/// Every value reachable from `stones` with the first blink it can show up on, in the order
+/// they're first seen, or `NotClosed` if there are more than `limit`.
+fn reachable_values(stones: &[u64], rules: &Rules, limit: usize) -> Result<Vec<(u64, u64)>, PuzzleError> {
+    let mut seen: HashSet<u64> = HashSet::new();
+    let mut values = Vec::new();
+    let mut queue: VecDeque<(u64, u64)> = stones.iter().map(|&s| (s, 0)).collect();
+
+    while let Some((v, blink)) = queue.pop_front() {
+        if !seen.insert(v) {
+            continue;
+        }
+
+        if values.len() == limit {
+            return Err(PuzzleError::NotClosed(limit));
+        }
+
+        values.push((v, blink));
+        queue.extend(rules.apply(v)?.into_iter().map(|next| (next, blink + 1)));
+    }
+
+    Ok(values)
+}
+
+/// Square matrix stored row-major.
+#[derive(Debug, Clone)]
+struct Matrix {
+    n: usize,
+    cells: Vec<u64>,
+}
+
+impl Matrix {
+    fn identity(n: usize) -> Self {
+        let mut cells = vec![0; n * n];
+        (0..n).for_each(|i| cells[i * n + i] = 1);
+        Matrix { n, cells }
+    }
+
+    fn mul(&self, other: &Matrix, counter: Counter) -> Result<Matrix, PuzzleError> {
+        let n = self.n;
+        let mut cells = Vec::with_capacity(n * n);
+        let mut row = vec![0u128; n];
+        for i in 0..n {
+            row.fill(0);
+            for k in 0..n {
+                let a = self.cells[i * n + k] as u128;
+                if a == 0 {
+                    continue;
+                }
+
+                for (acc, &b) in row.iter_mut().zip(&other.cells[k * n..(k + 1) * n]) {
+                    let p = a * b as u128;
+                    *acc = match acc.checked_add(p) {
+                        Some(sum) => sum,
+                        None => counter.reduce(*acc)? as u128 + p,
+                    };
+                }
             }
+
+            for &acc in &row {
+                cells.push(counter.reduce(acc)?);
+            }
+        }
+
+        Ok(Matrix { n, cells })
+    }
+}
+
+/// Counts stones after `blinks` by raising the value-to-value transition matrix to that
+/// power, which takes log(blinks) multiplications. Only works when the reachable values
+/// form a closed set of at most `limit`.
+fn count_stones_matrix(
+    stones: &[u64],
+    rules: &Rules,
+    blinks: u64,
+    counter: Counter,
+    limit: usize,
+) -> Result<u64, PuzzleError> {
+    let values = reachable_values(stones, rules, limit)?.into_iter().map(|(v, _)| v).collect_vec();
+    let index: HashMap<u64, usize> = values.iter().enumerate().map(|(i, &v)| (v, i)).collect();
+    let n = values.len();
+
+    // Row `from` lists how many of each value one `from` stone becomes, so a row vector of
+    // counts times the matrix is one blink.
+    let mut step = Matrix { n, cells: vec![0; n * n] };
+    for (from, &v) in values.iter().enumerate() {
+        for to in rules.apply(v)? {
+            step.cells[from * n + index[&to]] += 1;
+        }
+    }
+
+    let mut power = Matrix::identity(n);
+    let mut remaining = blinks;
+    while remaining > 0 {
+        if remaining & 1 == 1 {
+            power = power.mul(&step, counter)?;
+        }
+
+        remaining >>= 1;
+        if remaining > 0 {
+            step = step.mul(&step, counter)?;
+        }
+    }
+
+    let row_sums: Vec<u64> = (0..n)
+        .map(|i| counter.sum(power.cells[i * n..(i + 1) * n].iter().copied()))
+        .try_collect()?;
+
+    counter.sum(stones.iter().map(|s| row_sums[index[s]]))
+}
+
+/// What the table looks like after one blink.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct BlinkStats {
+    blink: u64,
+    distinct: usize,
+    /// Values on the table that weren't on it after any earlier blink.
+    new: usize,
+    stones: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Lineage {
+    blinks: Vec<BlinkStats>,
+    /// Every reachable value and the first blink it appears on.
+    closure: Vec<(u64, u64)>,
+    /// The last blink that puts a never-before-seen value on the table.
+    growth_stops: u64,
+    /// How many of the final stones descend from each input stone, in input order.
+    contributions: Vec<(u64, u64)>,
+}
+
+/// Follows the stones for `blinks` blinks, keeping track of which values show up when and
+/// which input stones the final count comes from.
+fn lineage(stones: &[u64], rules: &Rules, blinks: u64, counter: Counter, closure_limit: usize) -> Result<Lineage, PuzzleError> {
+    let closure = reachable_values(stones, rules, closure_limit)?;
+    let growth_stops = closure.iter().map(|&(_, blink)| blink).max().unwrap_or(0);
+
+    let mut seen = HashSet::new();
+    let mut number_to_count = initial_counts(stones, counter)?;
+    let mut stats = Vec::new();
+    for blink in 0..=blinks {
+        if blink > 0 {
+            number_to_count = blink_counts(number_to_count, rules, counter)?;
         }
 
-        number_to_count = new_number_to_count;
+        stats.push(BlinkStats {
+            blink,
+            distinct: number_to_count.len(),
+            new: number_to_count.keys().filter(|&&v| seen.insert(v)).count(),
+            stones: counter.sum(number_to_count.values().copied())?,
+        });
+    }
+
+    let mut per_value = HashMap::new();
+    let contributions = stones.iter()
+        .map(|&s| {
+            if let Some(&count) = per_value.get(&s) {
+                return Ok((s, count));
+            }
+
+            let count = count_stones(&[s], rules, blinks, counter)?;
+            per_value.insert(s, count);
+            Ok((s, count))
+        })
+        .try_collect()?;
+
+    Ok(Lineage { blinks: stats, closure, growth_stops, contributions })
+}
+
+fn write_lineage_csv(lineage: &Lineage, dir: &Path) -> std::io::Result<()> {
+    fs::create_dir_all(dir)?;
+
+    let mut blinks = String::from("blink,distinct,new,stones\n");
+    for b in &lineage.blinks {
+        writeln!(blinks, "{},{},{},{}", b.blink, b.distinct, b.new, b.stones).unwrap();
+    }
+    fs::write(dir.join("blinks.csv"), blinks)?;
+
+    let mut closure = String::from("value,first_blink\n");
+    for (value, blink) in &lineage.closure {
+        writeln!(closure, "{value},{blink}").unwrap();
     }
+    fs::write(dir.join("closure.csv"), closure)?;
 
-    number_to_count.iter().map(|v| *v.1).sum::<u64>()
+    let mut stones = String::from("index,stone,descendants\n");
+    for (i, (stone, count)) in lineage.contributions.iter().enumerate() {
+        writeln!(stones, "{i},{stone},{count}").unwrap();
+    }
+    fs::write(dir.join("stones.csv"), stones)
+}
+
+/// Lineage follows every reachable value, so it only gives up on rules that keep making new
+/// ones.
+const LINEAGE_CLOSURE_LIMIT: usize = 1 << 20;
+
+#[derive(Parser, Debug)]
+struct Args {
+    #[arg(default_value = "input_sample.txt")]
+    input_file: String,
+
+    /// Rules like "=0 -> set 1; even-digits -> split; any -> mul 2024". Predicates are
+    /// any, =N, even-digits, odd-digits and div N; transforms are set N, split, mul [N] and
+    /// add N. The first matching rule applies.
+    #[arg(short, long)]
+    rules: Option<String>,
+
+    /// Multiplier for the standard rules and for a bare `mul`.
+    #[arg(short, long, default_value_t = 2024)]
+    multiplier: u64,
+
+    /// Base that digits are counted and split in.
+    #[arg(long, default_value_t = 10, value_parser = clap::value_parser!(u64).range(2..))]
+    base: u64,
+
+    /// Also count the stones after this many blinks.
+    #[arg(short, long)]
+    blinks: Option<u64>,
+
+    /// Report counts for --blinks modulo this.
+    #[arg(long, value_parser = clap::value_parser!(u64).range(2..))]
+    modulus: Option<u64>,
+
+    /// Count --blinks by matrix exponentiation over the reachable values.
+    #[arg(long, default_value_t = false)]
+    matrix: bool,
+
+    /// Write blinks.csv, closure.csv and stones.csv to this directory, following the stones
+    /// for --blinks blinks (75 if not given).
+    #[arg(short, long)]
+    lineage: Option<PathBuf>,
+
+    /// Most reachable values the matrix path will take on. Single-digit stones reach about
+    /// 50 under the standard rules, but most multi-digit ones reach thousands, where the
+    /// cubic matrix products take too long.
+    #[arg(long, default_value_t = 1000)]
+    closure_limit: usize,
 }
 
 fn main() -> Result<(), Box<dyn Error>> {
-    let file_name = env::args().nth(1).unwrap_or("input_sample.txt".into());
+    let args = Args::parse();
 
-    let str = fs::read_to_string(file_name)?;
+    let str = fs::read_to_string(&args.input_file)?;
     let stones: Vec<u64> = str
         .trim_ascii()
         .split_ascii_whitespace()
@@ -131,28 +544,104 @@ fn main() -> Result<(), Box<dyn Error>> {
 
     let puzzle = Puzzle{stones};
 
-    //let part1_6 = time_it("part1 (6)", || solve_part1(&puzzle, 6, true));
-    //dbg!(part1_6);
+    let rules = match &args.rules {
+        Some(text) => Rules::parse(text, args.multiplier, args.base)?,
+        None => Rules::standard(args.multiplier, args.base),
+    };
+    println!("rules: {rules}");
 
-
-    let part1 = time_it("part1", || solve_part1(&puzzle.stones, 25, false));
+    let part1 = time_it("part1", || solve_part1(&puzzle.stones, &rules, 25, false))?;
     dbg!(part1);
 
-    /*
-    let mut v = puzzle.stones[0];
-    let mut v2 = None;
-    dbg!(v);
-    for i in 0..75 {
-        let new = stone_rule(v);
-        let new2 = v2.map(|s| stone_rule(s).0).or(new.1);
+    let part2 = time_it("part2", || solve_part2(&puzzle, &rules, 75))?;
+    dbg!(part2);
 
-        v = new.0;
-        v2 = new2;
-        println!("{i}: {v:?} {v2:?}");
+    if let Some(blinks) = args.blinks {
+        let counter = Counter { modulus: args.modulus };
+        let count = if args.matrix {
+            time_it("matrix", || count_stones_matrix(&puzzle.stones, &rules, blinks, counter, args.closure_limit))?
+        } else {
+            time_it("count map", || count_stones(&puzzle.stones, &rules, blinks, counter))?
+        };
+
+        match args.modulus {
+            Some(m) => println!("{blinks} blinks: {count} (mod {m})"),
+            None => println!("{blinks} blinks: {count}"),
+        }
+    }
+
+    if let Some(dir) = &args.lineage {
+        let blinks = args.blinks.unwrap_or(75);
+        let counter = Counter { modulus: args.modulus };
+        let lineage = time_it("lineage", || lineage(&puzzle.stones, &rules, blinks, counter, LINEAGE_CLOSURE_LIMIT))?;
+        write_lineage_csv(&lineage, dir)?;
+
+        println!("{} reachable values, the last new one appears on blink {}", lineage.closure.len(), lineage.growth_stops);
+        println!("wrote lineage for {blinks} blinks to {}", dir.display());
     }
-    */
 
-    let part2 = time_it("part2", || solve_part2(&puzzle, 75));
-    dbg!(part2);
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sample() {
+        let rules = Rules::standard(2024, 10);
+        assert_eq!(solve_part1(&[0, 1, 10, 99, 999], &rules, 1, false), Ok(7));
+        assert_eq!(solve_part1(&[125, 17], &rules, 6, false), Ok(22));
+        assert_eq!(solve_part2(&Puzzle { stones: vec![125, 17] }, &rules, 25), Ok(55312));
+    }
+
+    #[test]
+    fn parses_rules() {
+        let rules = Rules::parse("=0 -> set 1; even-digits -> split; any -> mul", 2024, 10).unwrap();
+        assert_eq!(rules, Rules::standard(2024, 10));
+        assert_eq!(Rules::parse(&rules.to_string().replace(" (base 10)", ""), 7, 10), Ok(rules));
+        assert_eq!(Rules::parse("=0 -> explode", 2024, 10), Err(PuzzleError::BadRule("=0 -> explode".into())));
+    }
+
+    #[test]
+    fn matrix_matches_count_map() {
+        let rules = Rules::standard(2024, 10);
+        let stones = [0, 1, 2, 8];
+        let exact = Counter { modulus: None };
+        for blinks in [0, 1, 6, 25, 40] {
+            assert_eq!(
+                count_stones_matrix(&stones, &rules, blinks, exact, 1000),
+                count_stones(&stones, &rules, blinks, exact));
+        }
+
+        let modular = Counter { modulus: Some(1_000_000_007) };
+        assert_eq!(
+            count_stones_matrix(&stones, &rules, 300, modular, 1000),
+            count_stones(&stones, &rules, 300, modular));
+
+        assert!(count_stones_matrix(&[0, 1, 10, 99, 999], &rules, 75, exact, 1000).is_err());
+
+        let growing = Rules::parse("any -> add 1", 2024, 10).unwrap();
+        assert_eq!(count_stones_matrix(&[1], &growing, 5, exact, 50), Err(PuzzleError::NotClosed(50)));
+    }
+
+    #[test]
+    fn lineage_adds_up() {
+        let rules = Rules::standard(2024, 10);
+        let exact = Counter { modulus: None };
+        let lineage = lineage(&[125, 17], &rules, 25, exact, 100_000).unwrap();
+
+        assert_eq!(lineage.blinks.iter().take(7).map(|b| b.stones).collect_vec(), [2, 3, 4, 5, 9, 13, 22]);
+        assert_eq!(lineage.blinks[25].stones, 55312);
+        assert_eq!(lineage.contributions.iter().map(|c| c.1).sum::<u64>(), 55312);
+        assert_eq!(lineage.blinks[0].new, 2);
+
+        let new_by_blink = lineage.blinks.iter().map(|b| b.new).collect_vec();
+        let first_seen = (0..=25).map(|b| lineage.closure.iter().filter(|c| c.1 == b).count()).collect_vec();
+        assert_eq!(new_by_blink, first_seen);
+
+        let single = super::lineage(&[1], &rules, 20, exact, 100_000).unwrap();
+        assert_eq!((single.closure.len(), single.growth_stops), (54, 15));
+        assert_eq!(single.blinks.iter().map(|b| b.new).sum::<usize>(), 54);
+    }
+}
@@ -0,0 +1,365 @@
+//! Regions of matching cells in a grid and the shape of their fences.
+
+use std::collections::BTreeSet;
+use std::collections::VecDeque;
+use std::iter::FusedIterator;
+use bit_set::BitSet;
+use itertools::izip;
+use itertools::Itertools;
+
+pub type Location = [usize;2];
+
+const DIRECTION: [[isize;2]; 4] = [
+    [0isize, -1isize],
+    [0isize, 1isize],
+    [-1isize, 0isize],
+    [1isize, 0isize]
+];
+
+const DIAGONAL: [[isize;2]; 4] = [
+    [-1isize, -1isize],
+    [-1isize, 1isize],
+    [1isize, -1isize],
+    [1isize, 1isize]
+];
+
+/// Which neighbors join cells into the same region.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum Connectivity {
+    /// Cells sharing an edge.
+    #[default]
+    Four,
+    /// Cells sharing an edge or a corner.
+    Eight,
+}
+
+#[allow(non_camel_case_types, clippy::upper_case_acronyms)]
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum DIRECTION_NAME {
+    W = 0,
+    E = 1,
+    N = 2,
+    S = 3,
+}
+
+#[derive(Debug, Default)]
+struct DirectionIterator {
+    location: Location,
+    rows: usize,
+    cols: usize,
+    current_dir: usize,
+}
+
+impl DirectionIterator {
+    fn step(&self, d: [isize;2]) -> Option<Location> {
+        let new_loc: [Option<usize>;2] = std::array::from_fn(|a| self.location[a].checked_add_signed(d[a]));
+        match new_loc {
+            [Some(r), Some(c)] if r < self.rows && c < self.cols => Some([r, c]),
+            _ => None,
+        }
+    }
+}
+
+impl Iterator for DirectionIterator {
+    type Item = Location;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.current_dir < DIRECTION.len() {
+            let d = DIRECTION[self.current_dir];
+            self.current_dir += 1;
+
+            if let Some(loc) = self.step(d) {
+                return Some(loc);
+            }
+        }
+
+        None
+    }
+}
+
+impl FusedIterator for DirectionIterator {}
+
+/// Like `DirectionIterator`, but yields `None` for directions that fall off the grid so the
+/// index still says which way each neighbor is.
+struct DirectionIteratorWithMissingDirections(DirectionIterator);
+
+impl Iterator for DirectionIteratorWithMissingDirections {
+    type Item = Option<Location>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let d = *DIRECTION.get(self.0.current_dir)?;
+        self.0.current_dir += 1;
+        Some(self.0.step(d))
+    }
+}
+
+struct Grid<'a> {
+    map: &'a [Vec<u8>],
+    rows: usize,
+    cols: usize,
+}
+
+impl Grid<'_> {
+    fn directions_at(&self, location: Location) -> DirectionIterator {
+        DirectionIterator{location, rows: self.rows, cols: self.cols, ..Default::default()}
+    }
+
+    fn directions_at_with_missing(&self, location: Location) -> DirectionIteratorWithMissingDirections {
+        DirectionIteratorWithMissingDirections(self.directions_at(location))
+    }
+
+    fn cell_index(&self, location: Location) -> usize {
+        assert!(location[0] < self.rows);
+        assert!(location[1] < self.cols);
+        location[0] * self.cols + location[1]
+    }
+
+    fn value_at(&self, location: Location) -> u8 {
+        self.map[location[0]][location[1]]
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RegionInfo {
+    /// The plant growing in the region.
+    pub id: char,
+    pub area: usize,
+    pub perimeter: usize,
+    /// Straight runs of fence, found by scanning rows and columns for where walls start.
+    pub sides: usize,
+    /// Turns in the fence, counted cell by cell. Every side runs between two corners, so
+    /// this always matches `sides`.
+    pub corners: usize,
+    /// Top-left and bottom-right cells, inclusive.
+    pub bounding_box: [Location; 2],
+    /// Regions that border only this one and don't reach the edge of the grid.
+    pub holes: Vec<usize>,
+    /// The region this one is a hole of.
+    pub enclosed_by: Option<usize>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Regions {
+    pub rows: usize,
+    pub cols: usize,
+    /// Row-major index into `regions` for every cell.
+    pub region_ids: Vec<usize>,
+    pub regions: Vec<RegionInfo>,
+}
+
+impl Regions {
+    /// The region-id grid, with every id padded to the same width.
+    pub fn render(&self) -> String {
+        let width = self.regions.len().saturating_sub(1).to_string().len();
+        (0..self.rows)
+            .map(|r| self.region_ids[r * self.cols..(r + 1) * self.cols].iter().map(|id| format!("{id:>width$}")).join(" "))
+            .join("\n")
+    }
+}
+
+/// Splits `map` into regions of equal cells. The fences are the same either way: a wall is any
+/// cell edge between a region and something else, and a side is a straight run of walls facing
+/// the same way, which only continues past a point if the cells on both sides of it are in the
+/// region. Where region cells touch only at a corner, their walls meet at that corner rather
+/// than running through it.
+pub fn find_regions(map: &[Vec<u8>], connectivity: Connectivity) -> Regions {
+    let rows = map.len();
+    let cols = map.first().map_or(0, |r| r.len());
+    assert!(map.iter().all(|r| r.len() == cols));
+    let grid = Grid { map, rows, cols };
+
+    let mut regions: Vec<RegionInfo> = Vec::new();
+    let mut region_ids: Vec<Option<usize>> = vec![None; rows * cols];
+    let mut wall_at = vec![[false; 4]; rows * cols];
+    let mut bfs_queue = VecDeque::new();
+
+    for r in 0..rows {
+        for c in 0..cols {
+            if region_ids[grid.cell_index([r, c])].is_some() {
+                continue;
+            }
+
+            let id = grid.value_at([r, c]);
+            let region_id = regions.len();
+            let mut region_info = RegionInfo {
+                id: id as char,
+                area: 0,
+                perimeter: 0,
+                sides: 0,
+                corners: 0,
+                bounding_box: [[r, c], [r, c]],
+                holes: Vec::new(),
+                enclosed_by: None,
+            };
+
+            bfs_queue.push_back([r, c]);
+            while let Some(loc) = bfs_queue.pop_front() {
+                let index = grid.cell_index(loc);
+                if region_ids[index].is_some() { continue };
+
+                if connectivity == Connectivity::Eight {
+                    bfs_queue.extend(DIAGONAL.iter()
+                        .filter_map(|&d| grid.directions_at(loc).step(d))
+                        .filter(|&l| grid.value_at(l) == id));
+                }
+
+                region_info.area += 1;
+                let [min, max] = &mut region_info.bounding_box;
+                *min = [min[0].min(loc[0]), min[1].min(loc[1])];
+                *max = [max[0].max(loc[0]), max[1].max(loc[1])];
+
+                for (dir, neighbor) in grid.directions_at_with_missing(loc).enumerate() {
+                    match neighbor {
+                        Some(l) if grid.value_at(l) == id => bfs_queue.push_back(l),
+                        _ => {
+                            region_info.perimeter += 1;
+                            wall_at[index][dir] = true;
+                        }
+                    }
+                }
+
+                region_ids[index] = Some(region_id);
+            }
+
+            regions.push(region_info);
+        }
+    }
+
+    let region_ids = region_ids.into_iter().map(Option::unwrap).collect::<Vec<_>>();
+
+    count_sides(&grid, &region_ids, &wall_at, &mut regions);
+    count_corners(&grid, &region_ids, &mut regions);
+    find_holes(&grid, &region_ids, &mut regions);
+
+    Regions { rows, cols, region_ids, regions }
+}
+
+/// Scans each row for runs of north and south walls and each column for west and east ones,
+/// counting a side wherever a run starts.
+fn count_sides(grid: &Grid, region_ids: &[usize], wall_at: &[[bool; 4]], regions: &mut [RegionInfo]) {
+    #[derive(Debug)]
+    struct WallFindState {
+        dirs: [DIRECTION_NAME; 2],
+        prev_region_id: Option<usize>,
+        prev_walls: [bool; 2]
+    }
+
+    impl WallFindState {
+        fn new(dirs: [DIRECTION_NAME; 2]) -> Self {
+            WallFindState{dirs, prev_region_id: Default::default(), prev_walls: Default::default()}
+        }
+
+        fn reset(&mut self) {
+            self.prev_region_id = None;
+            self.prev_walls = Default::default();
+        }
+    }
+
+    let extend_wall = |r, c, wall_finder: &mut WallFindState, regions: &mut [RegionInfo]| {
+        let index = grid.cell_index([r, c]);
+        let region_id = region_ids[index];
+        if wall_finder.prev_region_id.is_none_or(|id| id != region_id) {
+            wall_finder.reset();
+            wall_finder.prev_region_id = Some(region_id);
+        }
+
+        let new_walls = wall_finder.dirs.map(|dir| wall_at[index][dir as usize]);
+        for (old, new) in izip!(wall_finder.prev_walls, new_walls) {
+            if !old && new {
+                regions[region_id].sides += 1;
+            }
+        }
+
+        wall_finder.prev_walls = new_walls;
+    };
+
+    let mut wall_finder = WallFindState::new([DIRECTION_NAME::N, DIRECTION_NAME::S]);
+    for r in 0..grid.rows {
+        wall_finder.reset();
+        for c in 0..grid.cols {
+            extend_wall(r, c, &mut wall_finder, regions);
+        }
+    }
+
+    let mut wall_finder = WallFindState::new([DIRECTION_NAME::W, DIRECTION_NAME::E]);
+    for c in 0..grid.cols {
+        wall_finder.reset();
+        for r in 0..grid.rows {
+            extend_wall(r, c, &mut wall_finder, regions);
+        }
+    }
+}
+
+/// Looks at each corner of each cell: it's a fence corner if both edges next to it are walls
+/// (outside corner), or neither is but the diagonal cell is in another region (inside corner).
+fn count_corners(grid: &Grid, region_ids: &[usize], regions: &mut [RegionInfo]) {
+    for r in 0..grid.rows {
+        for c in 0..grid.cols {
+            let cell = DirectionIterator{location: [r, c], rows: grid.rows, cols: grid.cols, ..Default::default()};
+            let region_id = region_ids[grid.cell_index([r, c])];
+            let same = |d: [isize; 2]| cell.step(d).is_some_and(|l| region_ids[grid.cell_index(l)] == region_id);
+
+            for [dr, dc] in [[-1, -1], [-1, 1], [1, -1], [1, 1]] {
+                let vertical = same([dr, 0]);
+                let horizontal = same([0, dc]);
+                if (!vertical && !horizontal) || (vertical && horizontal && !same([dr, dc])) {
+                    regions[region_id].corners += 1;
+                }
+            }
+        }
+    }
+}
+
+/// A region is a hole when it stays off the edge and everything around its outside belongs
+/// to one other region. Cells on the outside are found by flooding in from just beyond its
+/// bounding box, so regions nested inside it don't count. The flood only crosses edges, since
+/// an 8-connected region can't be slipped past at a corner either, and only cells sharing an
+/// edge with the region count as around it.
+fn find_holes(grid: &Grid, region_ids: &[usize], regions: &mut [RegionInfo]) {
+    let mut touches_edge = vec![false; regions.len()];
+    for r in 0..grid.rows {
+        for c in 0..grid.cols {
+            if r == 0 || c == 0 || r + 1 == grid.rows || c + 1 == grid.cols {
+                touches_edge[region_ids[grid.cell_index([r, c])]] = true;
+            }
+        }
+    }
+
+    let mut visited = BitSet::with_capacity(grid.rows * grid.cols);
+    let mut bfs_queue = VecDeque::new();
+    for region_id in (0..regions.len()).filter(|&id| !touches_edge[id]) {
+        let [[top, left], [bottom, right]] = regions[region_id].bounding_box;
+        let (top, left, bottom, right) = (top - 1, left - 1, bottom + 1, right + 1);
+
+        visited.clear();
+        for r in top..=bottom {
+            for c in left..=right {
+                if r == top || r == bottom || c == left || c == right {
+                    bfs_queue.push_back([r, c]);
+                }
+            }
+        }
+
+        let mut outside = BTreeSet::new();
+        while let Some(loc) = bfs_queue.pop_front() {
+            let index = grid.cell_index(loc);
+            if region_ids[index] == region_id || !visited.insert(index) {
+                continue;
+            }
+
+            for l in grid.directions_at(loc) {
+                if region_ids[grid.cell_index(l)] == region_id {
+                    outside.insert(region_ids[index]);
+                } else if (top..=bottom).contains(&l[0]) && (left..=right).contains(&l[1]) {
+                    bfs_queue.push_back(l);
+                }
+            }
+        }
+
+        if outside.len() == 1 {
+            let outer = *outside.first().unwrap();
+            regions[region_id].enclosed_by = Some(outer);
+            regions[outer].holes.push(region_id);
+        }
+    }
+}
@@ -1,20 +1,15 @@
 use core::fmt;
-use std::env;
-use std::error;
 use std::fs;
 use std::error::Error;
 use std::io::Write;
-use std::path::Display;
+use std::ops::Range;
 use std::result::Result;
-use std::thread::sleep;
 use std::time::Duration;
 use crossterm::event::Event;
 use crossterm::event::KeyCode;
-use nalgebra;
 use ratatui::prelude::*;
 use ratatui::crossterm::event;
 
-use nalgebra::dimension;
 use nalgebra::Vector2;
 use ratatui::widgets::canvas::Canvas;
 use ratatui::widgets::canvas::Points;
@@ -61,7 +56,21 @@ fn simulate_simple(robots: &mut [Robot], dimensions: &Vector2<i64>, step_count:
     //draw_grid_at_step(robots, dimensions, step_count);
 }
 
-fn draw_grid_at_step(robots: &[Robot], dimensions: &Vector2<i64>, step_count: usize) {
+/// Robot positions after `step` ticks, computed directly rather than by stepping.
+/// The field is toroidal, so each axis only depends on `step` modulo its size.
+fn positions_at(robots: &[Robot], dimensions: &Vector2<i64>, step: u64) -> Vec<Robot> {
+    robots.iter()
+        .map(|r| {
+            let p = r.p.zip_zip_map(&r.v, dimensions, |p, v, d| {
+                let step = (step % d as u64) as i64;
+                (p + v.rem_euclid(d) * step).rem_euclid(d)
+            });
+            Robot{ p, v: r.v }
+        })
+        .collect()
+}
+
+fn draw_grid_at_step(robots: &[Robot], dimensions: &Vector2<i64>, step_count: u64) {
     println!("Step: {step_count}");
     let (rows, cols) = (dimensions.y as usize, dimensions.x as usize);
     let mut grid = vec![b'.'; rows * cols];
@@ -74,8 +83,8 @@ fn draw_grid_at_step(robots: &[Robot], dimensions: &Vector2<i64>, step_count: us
 
     for r in 0..rows {
         let to_write = &grid[(r * cols)..((r + 1) * cols)];
-        std::io::stdout().write(to_write).expect("write to stdout failed");
-        std::io::stdout().write(&[b'\n']).expect("write to stdout failed");
+        std::io::stdout().write_all(to_write).expect("write to stdout failed");
+        std::io::stdout().write_all(b"\n").expect("write to stdout failed");
     }
 }
 
@@ -86,30 +95,34 @@ fn fix_velocities(robots: &mut [Robot], dimensions: Vector2<i64>)
     }
 }
 
-fn score_part1(robots: &[Robot], dimension: Vector2<i64>) -> i64 {
-    let half_range = |d| [0..(d/2), (d/2+1)..d];
-
-    let x_ranges = half_range(dimension.x);
-    let y_ranges = half_range(dimension.y);
+/// Split `0..d` into `n` ranges, dropping the dividing line between each pair.
+/// With `n == 2` this is the usual halving around the middle row or column.
+fn partition_ranges(d: i64, n: i64) -> Vec<Range<i64>> {
+    let mut ranges = Vec::new();
+    let mut start = 0;
+    for k in 1..n {
+        let line = k * d / n;
+        ranges.push(start..line);
+        start = line + 1;
+    }
+    ranges.push(start..d);
+    ranges
+}
 
-    /*
-    let quadrant_ranges = [(x_ranges[0].clone(), y_ranges[0].clone()),
-                                                          (x_ranges[1].clone(), y_ranges[0].clone()),
-                                                          (x_ranges[1].clone(), y_ranges[0].clone()),
-                                                          (x_ranges[1].clone(), y_ranges[1].clone())];
-    */
+fn score_part1(robots: &[Robot], dimension: Vector2<i64>, partition: Vector2<i64>) -> i64 {
+    let x_ranges = partition_ranges(dimension.x, partition.x);
+    let y_ranges = partition_ranges(dimension.y, partition.y);
 
-    let mut quadrant_count = [0i64;4];
+    let mut quadrant_count = vec![0i64; x_ranges.len() * y_ranges.len()];
 
-    for (i, r) in robots.iter().enumerate() {
+    for r in robots.iter() {
         let x_quad = x_ranges.iter().position(|q| q.contains(&r.p.x));
         let y_quad = y_ranges.iter().position(|q| q.contains(&r.p.y));
         
-        // println!("{i}: {r}: {x_quad:?}, {y_quad:?}");
+        // println!("{r}: {x_quad:?}, {y_quad:?}");
 
-        match (x_quad, y_quad) {
-            (Some(x), Some(y)) => quadrant_count[y * 2 + x] += 1,
-            _ => ()
+        if let (Some(x), Some(y)) = (x_quad, y_quad) {
+            quadrant_count[y * x_ranges.len() + x] += 1;
         }
     }
 
@@ -121,8 +134,64 @@ fn score_part1(robots: &[Robot], dimension: Vector2<i64>) -> i64 {
 
 }
 
+fn infer_dimensions(robots: &[Robot]) -> Vector2<i64> {
+    robots.iter()
+        .fold(Vector2::new(0, 0), |acc, r| acc.zip_map(&r.p, |a, p| a.max(p + 1)))
+}
+
+fn parse_dimensions(s: &str) -> Result<Vector2<i64>, String> {
+    let (x, y) = s.split_once(['x', ','])
+        .ok_or_else(|| format!("expected WIDTHxHEIGHT, got '{s}'"))?;
+    let parse = |v: &str| v.trim().parse::<i64>()
+        .ok()
+        .filter(|&v| v > 0)
+        .ok_or_else(|| format!("invalid dimension '{v}'"));
+    Ok(Vector2::new(parse(x)?, parse(y)?))
+}
+
 #[cfg(test)]
 mod tests{
+    use super::*;
+
+    #[test]
+    fn partition_matches_halves() {
+        assert_eq!(partition_ranges(11, 2), vec![0..5, 6..11]);
+        assert_eq!(partition_ranges(103, 2), vec![0..51, 52..103]);
+        assert_eq!(partition_ranges(10, 1), vec![0..10]);
+        assert_eq!(partition_ranges(11, 3), vec![0..3, 4..7, 8..11]);
+    }
+
+    #[test]
+    fn sample_score() {
+        let robots = parse_robots(&std::fs::read_to_string("input_sample.txt").unwrap());
+        let dimensions = infer_dimensions(&robots);
+        assert_eq!(dimensions, Vector2::new(11, 7));
+
+        let mut robots = robots;
+        fix_velocities(&mut robots, dimensions);
+        simulate_simple(&mut robots, &dimensions, 100);
+        assert_eq!(score_part1(&robots, dimensions, Vector2::new(2, 2)), 12);
+    }
+
+    #[test]
+    fn positions_at_matches_simulation() {
+        let robots = parse_robots(&std::fs::read_to_string("input_sample.txt").unwrap());
+        let dimensions = Vector2::new(11, 7);
+
+        let mut simulated = robots.clone();
+        fix_velocities(&mut simulated, dimensions);
+        for step in 0..200 {
+            let direct = positions_at(&robots, &dimensions, step);
+            assert!(direct.iter().zip(&simulated).all(|(a, b)| a.p == b.p), "mismatch at step {step}");
+            solve_naive_step(&mut simulated, &dimensions);
+        }
+
+        let period = (dimensions.x * dimensions.y) as u64;
+        let far = 1_000_000_000_000u64;
+        let a = positions_at(&robots, &dimensions, far);
+        let b = positions_at(&robots, &dimensions, far % period);
+        assert!(a.iter().zip(&b).all(|(a, b)| a.p == b.p));
+    }
 
     #[test]
     fn negative_modulus() {
@@ -133,7 +202,9 @@ mod tests{
 
 }
 
-fn simulate_ratatui(robots: &mut Vec<Robot>, dimensions: Vector2<i64>, start_step_no: usize, args: &Args) -> std::io::Result<()>
+/// Key bindings: `q` quits, Left/Right seek one step, Down/Up seek 100 steps and
+/// PageDown/PageUp seek 10000 steps. Any other key just advances one step.
+fn simulate_ratatui(robots: &[Robot], dimensions: Vector2<i64>, start_step_no: u64, args: &Args) -> std::io::Result<()>
 {
         // all coordinates are reversed for x and y, since the TUI coordinate system is different
     // from the puzzle coordinate system.
@@ -142,9 +213,10 @@ fn simulate_ratatui(robots: &mut Vec<Robot>, dimensions: Vector2<i64>, start_ste
     let mut terminal = ratatui::init();
 
     loop {
+        let robots = positions_at(robots, &dimensions, step_no);
         let points = robots.iter().map(|r| (r.p.x as f64, (dimensions.y - r.p.y - 1) as f64)).collect::<Vec<_>>();
 
-        let score = score_part1(&robots, dimensions);
+        let score = score_part1(&robots, dimensions, args.partition);
         let mut event = None;
 
         if args.render_threshold.is_none_or(|rt| rt >= score) {
@@ -177,15 +249,21 @@ fn simulate_ratatui(robots: &mut Vec<Robot>, dimensions: Vector2<i64>, start_ste
             event = Some(event::read()?);
         }
 
+        let mut seek = 1i64;
         if let Some(Event::Key(key_event)) = event {
-            if key_event.code == KeyCode::Char('q') {
-                break;
-            }            
+            seek = match key_event.code {
+                KeyCode::Char('q') => break,
+                KeyCode::Left => -1,
+                KeyCode::Down => 100,
+                KeyCode::Up => -100,
+                KeyCode::PageDown => 10000,
+                KeyCode::PageUp => -10000,
+                _ => 1,
+            };
         }
 
         //sleep(Duration::from_millis(200));
-        solve_naive_step(robots.as_mut_slice(), &dimensions);
-        step_no += 1;
+        step_no = step_no.saturating_add_signed(seek);
     }
 
     //terminal.clear()?;
@@ -205,14 +283,26 @@ struct Args {
     #[arg(short, long, default_value_t = false)]
     is_sample: bool,
 
+    /// Field size as WIDTHxHEIGHT, overriding --is-sample.
+    #[arg(short, long, value_parser = parse_dimensions)]
+    dimensions: Option<Vector2<i64>>,
+
+    /// Size the field from the input (max coordinate + 1) instead of the puzzle constants.
+    #[arg(long, default_value_t = false)]
+    infer_dimensions: bool,
+
+    /// Number of columns and rows of sectors to score, as COLSxROWS.
+    #[arg(short, long, value_parser = parse_dimensions, default_value = "2x2")]
+    partition: Vector2<i64>,
+
     #[arg(long, default_value_t = false)]
     tui: bool,
 
     #[arg(short, long, default_value_t = 0)]
-    start_step: usize,
+    start_step: u64,
 
     #[arg(short, long)]
-    num_steps: Option<usize>,
+    num_steps: Option<u64>,
 
     #[arg(short='t', long)]
     score_threshold: Option<i64>,
@@ -221,12 +311,8 @@ struct Args {
     render_threshold: Option<i64>,
 }
 
-fn main() -> Result<(), Box<dyn Error>> {
-    let args = Args::parse();
-
-    let str = fs::read_to_string(&args.input_file)?;
-
-    let robots_original = str.trim_ascii()
+fn parse_robots(input: &str) -> Vec<Robot> {
+    input.trim_ascii()
         .lines()
         .map(
             |s| {
@@ -236,10 +322,24 @@ fn main() -> Result<(), Box<dyn Error>> {
                     v: Vector2::new(r.2, r.3)
                 }
             }
-        ).collect::<Vec<_>>();
+        ).collect::<Vec<_>>()
+}
 
-    let dimensions = if (args.is_sample) { DIM_SAMPLE } else { DIM_PUZZLE };
-    let dimensions = Vector2::new(dimensions.0, dimensions.1);
+fn main() -> Result<(), Box<dyn Error>> {
+    let args = Args::parse();
+
+    let str = fs::read_to_string(&args.input_file)?;
+
+    let robots_original = parse_robots(&str);
+
+    let dimensions = if let Some(dimensions) = args.dimensions {
+        dimensions
+    } else if args.infer_dimensions {
+        infer_dimensions(&robots_original)
+    } else {
+        let dimensions = if args.is_sample { DIM_SAMPLE } else { DIM_PUZZLE };
+        Vector2::new(dimensions.0, dimensions.1)
+    };
     
     let mut robots = robots_original.clone();
     fix_velocities(&mut robots, dimensions);
@@ -247,19 +347,15 @@ fn main() -> Result<(), Box<dyn Error>> {
     simulate_simple(&mut robots, &dimensions, 100);
     let robots_part1 = robots.clone();
 
-    let mut robots = robots_original.clone();
-
-
     if args.tui {
-        simulate_simple(&mut robots, &dimensions, args.start_step);
-        simulate_ratatui(&mut robots, dimensions, args.start_step, &args)?;
+        simulate_ratatui(&robots_original, dimensions, args.start_step, &args)?;
     } else {
-        simulate_simple(&mut robots, &dimensions, args.start_step);
+        let robots = positions_at(&robots_original, &dimensions, args.start_step);
         draw_grid_at_step(&robots, &dimensions, args.start_step);
         //simulate_ratatui(&mut robots, dimensions, start_step_no)?;
     }
 
-    let part1 = score_part1(&robots_part1, dimensions);
+    let part1 = score_part1(&robots_part1, dimensions, args.partition);
     dbg!(part1);
     Ok(())
 }
@@ -1,16 +1,14 @@
-use core::fmt;
-use std::collections::VecDeque;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashSet, VecDeque};
 use std::fs;
 use std::error::Error;
 use std::num::Saturating;
-use std::u64;
 use itertools::Itertools;
 use neerajsi::Iterable2d;
 use clap::Parser;
 use thiserror::Error;
 use num_derive::FromPrimitive;
 use num_traits::FromPrimitive;
-use nalgebra::Vector2;
 use neerajsi::*;
 
 use neerajsi::CardinalDirectionName as DirectionName;
@@ -18,6 +16,7 @@ use neerajsi::next_pos_cardinal as next_pos;
 
 #[cfg(test)]
 mod tests{
+    use super::*;
 
     #[test]
     fn negative_modulus() {
@@ -26,6 +25,55 @@ mod tests{
         println!("{x} % {y} = {}", x % y);
     }
 
+    #[test]
+    fn best_paths_cover_best_tiles() {
+        let args = Args::parse_from(["day16"]);
+        for (input, cost, tiles, count) in [
+            (include_str!("../input_sample.txt"), 7036, 45, 3),
+            (include_str!("../input_sample2.txt"), 11048, 64, 2),
+        ] {
+            let puzzle = parse_puzzle(input).unwrap();
+            let best = solve_part1_bfs(&puzzle, &args);
+            assert_eq!(best.cost, cost);
+            assert_eq!(solve_part2(&best), tiles);
+
+            let paths = enumerate_best_paths(&puzzle, &best, usize::MAX);
+            assert_eq!(paths.len(), count);
+            assert!(paths.iter().all(|p| path_cost(p, &best.costs) == cost));
+            assert_eq!(paths.iter().flatten().map(|(pos, _)| pos).unique().count(), tiles);
+            assert_eq!(enumerate_best_paths(&puzzle, &best, 1).len(), 1);
+        }
+    }
+
+    #[test]
+    fn k_shortest_starts_with_best_paths() {
+        let puzzle = parse_puzzle(include_str!("../input_sample.txt")).unwrap();
+        for (step, turn) in [(1, 1000), (1, 0), (5, 7)] {
+            let args = Args::parse_from(["day16", "--step-cost", &step.to_string(), "--turn-cost", &turn.to_string()]);
+            let best = solve_part1_bfs(&puzzle, &args);
+            let best_paths = enumerate_best_paths(&puzzle, &best, usize::MAX);
+
+            let routes = k_shortest_paths(&puzzle, &best.costs, best_paths.len() + 3);
+            let route_costs = routes.iter().map(|p| path_cost(p, &best.costs)).collect_vec();
+            assert!(route_costs.is_sorted(), "{route_costs:?}");
+            assert!(route_costs[..best_paths.len()].iter().all(|&c| c == best.cost), "{route_costs:?}");
+            assert!(route_costs[best_paths.len()] > best.cost, "{route_costs:?}");
+
+            let tiles = |paths: &[Path]| paths.iter().map(|p| p.iter().map(|(pos, _)| *pos).collect_vec()).sorted_by_key(|t| format!("{t:?}")).collect_vec();
+            assert_eq!(tiles(&routes[..best_paths.len()]), tiles(&best_paths));
+            assert_eq!(tiles(&routes).iter().unique().count(), routes.len());
+        }
+    }
+
+    #[test]
+    fn turn_sequence_runs() {
+        use DirectionName::*;
+        let path = [(0, E), (1, N), (2, N), (3, W), (4, W)]
+            .map(|(y, d)| (to_vector2(&[0, y]), d));
+        assert_eq!(turn_sequence(&path), "1F L 2F L 1F");
+        assert_eq!(path_cost(&path, &Costs::default()), 4 + 2000);
+        assert_eq!(path_cost(&path, &Costs { step: 3, turn: 10 }), 12 + 20);
+    }
 }
 
 #[derive(Debug, Error)]
@@ -78,30 +126,46 @@ fn draw_map(map: &[Vec<MapSlot>], pos: VectorType, cells: &[Vec<CellInfo>]) {
 }
 
 
-fn draw_path(puzzle: &Puzzle, cells: &[Vec<CellInfo>]) {
-    let mut map: Vec<Vec<_>> = puzzle.map.iter()
-        .map(|r| r.iter()
-                .map(|s| char::from_u32(*s as isize as u32).unwrap()).collect()
-            )
-        .collect();
+/// A path through the maze as each cell visited and the direction the reindeer leaves it in.
+/// The final entry is the end tile, with the direction it was entered from.
+type Path = Vec<(VectorType, DirectionName)>;
 
+/// Follows the cheapest direction out of each cell found by the recursive solver.
+fn follow_costs(puzzle: &Puzzle, cells: &[Vec<CellInfo>], costs: &Costs) -> Path {
+    let mut path = Vec::new();
     let mut pos = puzzle.start;
-    let mut reached_end = false;
     let mut direction = DirectionName::E;
 
     for _ in 0..1000 {
-        if index2d!(map, pos) == 'E' {
-            reached_end = true;
+        if index2d!(puzzle.map, pos) == MapSlot::End {
+            path.push((pos, direction));
             break;
         }
 
         // Skip costing walls.
-        if index2d!(map, pos) == '#' {
+        if index2d!(puzzle.map, pos) == MapSlot::Wall {
             continue;
         }
 
-        let new_dir = cost_step(pos, cells, direction).1;
-        let arrow = match new_dir {
+        let new_dir = cost_step(pos, cells, direction, costs).1;
+        path.push((pos, new_dir));
+        (pos, direction) = (next_pos(pos, new_dir), new_dir);
+    }
+
+    path
+}
+
+fn draw_path(puzzle: &Puzzle, path: &[(VectorType, DirectionName)]) {
+    let mut map: Vec<Vec<_>> = puzzle.map.iter()
+        .map(|r| r.iter()
+                .map(|s| char::from_u32(*s as isize as u32).unwrap()).collect()
+            )
+        .collect();
+
+    let reached_end = path.last().is_some_and(|&(pos, _)| pos == puzzle.end);
+
+    for &(pos, direction) in path.iter().filter(|(pos, _)| *pos != puzzle.end) {
+        let arrow = match direction {
             DirectionName::E => '>',
             DirectionName::S => 'v',
             DirectionName::W => '<',
@@ -109,7 +173,6 @@ fn draw_path(puzzle: &Puzzle, cells: &[Vec<CellInfo>]) {
         };
 
         index2d!(map, pos) = arrow;
-        (pos, direction) = (next_pos(pos, new_dir), new_dir);
     }
 
     for r in map {
@@ -161,19 +224,12 @@ struct CellInfo {
     visited: VisitState,
 }
 
-fn cost_step(pos: VectorType, cells: &[Vec<CellInfo>], from_dir: DirectionName) -> (Cost, DirectionName) {
+fn cost_step(pos: VectorType, cells: &[Vec<CellInfo>], from_dir: DirectionName, costs: &Costs) -> (Cost, DirectionName) {
     let VisitState::Done{min_costs} = index2d!(cells, pos).visited else { panic!("Cell at {pos:?} not visited!")};
     
     min_costs.iter().enumerate().map(|(d, &c)| {
-        let c = if from_dir as usize == d {
-            c
-        } else if opposite_dir(from_dir) as usize == d {
-            c + 1000 * 2
-        } else {
-            c + 1000
-        };
-
-        (c, DirectionName::from_usize(d).unwrap())
+        let d = DirectionName::from_usize(d).unwrap();
+        (c + costs.turn(from_dir, d), d)
     }).min_by_key(|v| v.0).unwrap()
 }
 
@@ -189,26 +245,122 @@ fn turns(direction: DirectionName) -> [DirectionName; 2]
 
 use neerajsi::opposite_dir_cardinal as opposite_dir;
 
-fn solve_part1_bfs(puzzle: &Puzzle, args: &Args) -> u64
-{
-    use DirectionName::*;
-    use MapSlot::*;
+/// What the reindeer pays to move forward one tile and to turn 90 degrees.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Costs {
+    step: u64,
+    turn: u64,
+}
+
+impl Default for Costs {
+    fn default() -> Self {
+        Costs { step: 1, turn: 1000 }
+    }
+}
+
+impl Costs {
+    /// The cost of turning from one heading to another.
+    fn turn(&self, from: DirectionName, to: DirectionName) -> u64 {
+        if from == to {
+            0
+        } else if opposite_dir(from) == to {
+            2 * self.turn
+        } else {
+            self.turn
+        }
+    }
 
-    #[derive(Debug, Clone)]
-    struct BfsCellInfo {
-        costs: [Saturating<u64>; 4],
-        in_queue: bool,
-        on_shortest_path: [bool; 4],
+    /// The cost of turning to `to` and then stepping forward.
+    fn turn_and_step(&self, from: DirectionName, to: DirectionName) -> u64 {
+        self.turn(from, to) + self.step
     }
+}
 
-    impl Default for BfsCellInfo {
-        fn default() -> Self {
-            BfsCellInfo{costs:[Saturating(u64::MAX); 4], in_queue: false, on_shortest_path: [false;4]}
+/// The score of a path: the steps plus the turns between them.
+fn path_cost(path: &[(VectorType, DirectionName)], costs: &Costs) -> u64 {
+    let mut direction = DirectionName::E;
+    let mut cost = 0;
+    for (i, &(_, d)) in path.iter().enumerate() {
+        cost += costs.turn(direction, d) + if i + 1 < path.len() { costs.step } else { 0 };
+        direction = d;
+    }
+
+    cost
+}
+
+/// Describes a path as runs of forward steps and the turns between them, like `3F R 2F`.
+fn turn_sequence(path: &[(VectorType, DirectionName)]) -> String {
+    use DirectionName::*;
+
+    let mut moves = Vec::new();
+    let mut direction = E;
+    let mut forward = 0;
+    for &(_, d) in path.iter().take(path.len().saturating_sub(1)) {
+        if d != direction {
+            if forward > 0 {
+                moves.push(format!("{forward}F"));
+                forward = 0;
+            }
+
+            let turn = match (direction, d) {
+                _ if opposite_dir(direction) == d => "U",
+                (N, E) | (E, S) | (S, W) | (W, N) => "R",
+                _ => "L",
+            };
+            moves.push(turn.to_string());
+            direction = d;
         }
+        forward += 1;
     }
 
+    if forward > 0 {
+        moves.push(format!("{forward}F"));
+    }
+
+    moves.join(" ")
+}
+
+#[derive(Debug, Clone)]
+struct BfsCellInfo {
+    costs: [Saturating<u64>; 4],
+    in_queue: bool,
+    on_shortest_path: [bool; 4],
+}
+
+impl Default for BfsCellInfo {
+    fn default() -> Self {
+        BfsCellInfo{costs:[Saturating(u64::MAX); 4], in_queue: false, on_shortest_path: [false;4]}
+    }
+}
+
+/// The result of the search back from the end: per cell, the cheapest cost to finish facing
+/// each direction and which of those states lie on some best path from the start.
+struct BestPaths {
+    cost: u64,
+    costs: Costs,
+    cells: Vec<Vec<BfsCellInfo>>,
+}
+
+impl BestPaths {
+    /// The moves out of `pos` facing `direction` that stay on a best path.
+    fn best_moves(&self, pos: VectorType, direction: DirectionName) -> impl Iterator<Item = DirectionName> + '_ {
+        let cost = index2d!(self.cells, pos).costs[direction as usize].0;
+        DIRECTIONS4.into_iter().filter_map(move |d| {
+            let d = DirectionName::from_usize(d as usize).unwrap();
+            let needed_cost = cost.checked_sub(self.costs.turn_and_step(direction, d))?;
+            (index2d!(self.cells, next_pos(pos, d)).costs[d as usize].0 == needed_cost).then_some(d)
+        })
+    }
+}
+
+fn solve_part1_bfs(puzzle: &Puzzle, args: &Args) -> BestPaths
+{
+    use DirectionName::*;
+    use MapSlot::*;
+
     let rows = puzzle.map.len();
     let cols = puzzle.map[0].len();
+    let costs = args.costs();
 
     let mut cells = vec![vec![BfsCellInfo::default(); cols]; rows];
 
@@ -230,7 +382,7 @@ fn solve_part1_bfs(puzzle: &Puzzle, args: &Args) -> u64
             }
 
             let source_dir = opposite_dir(d);
-            let cost_from = index2d!(cells, pos).costs[source_dir as usize] + Saturating(1);
+            let cost_from = index2d!(cells, pos).costs[source_dir as usize] + Saturating(costs.step);
 
             // let's see if we can decrease any costs.
 
@@ -238,15 +390,8 @@ fn solve_part1_bfs(puzzle: &Puzzle, args: &Args) -> u64
             let mut decreased = false;
             for (nd, cost) in neighbor_cell.costs.iter_mut().enumerate() {
                 let nd = DirectionName::from_usize(nd).unwrap();
-                let new_cost = 
-                    if nd == source_dir {
-                        cost_from
-                    } else if nd == d {
-                        cost_from + Saturating(2 * 1000)
-                    } else {
-                        assert!(turns(source_dir).contains(&nd));
-                        cost_from + Saturating(1000)
-                    };
+                assert!(nd == source_dir || nd == d || turns(source_dir).contains(&nd));
+                let new_cost = cost_from + Saturating(costs.turn(nd, source_dir));
 
                 if new_cost < *cost {
                     decreased = true;
@@ -276,11 +421,12 @@ fn solve_part1_bfs(puzzle: &Puzzle, args: &Args) -> u64
 
     dbg!(index2d!(cells, puzzle.start).costs);
 
-    let cost_from_start =  index2d!(cells, puzzle.start).costs[W as usize].0;
+    // The reindeer starts facing east.
+    let cost_from_start =  index2d!(cells, puzzle.start).costs[E as usize].0;
 
     dbg!(cost_from_start);
 
-    index2d!(cells, puzzle.start).on_shortest_path[W as usize] = true;
+    index2d!(cells, puzzle.start).on_shortest_path[E as usize] = true;
     index2d!(cells, puzzle.start).in_queue = true;
     index2d!(cells, puzzle.end).on_shortest_path = [true;4];
 
@@ -293,8 +439,8 @@ fn solve_part1_bfs(puzzle: &Puzzle, args: &Args) -> u64
         
         let incoming_shortest_paths = index2d!(cells, pos).on_shortest_path;
         for d1 in incoming_shortest_paths.iter().positions(|x| *x) {
-            let cost_from = index2d!(cells,pos).costs[d1].0 - 1;
-            assert_ne!(cost_from, u64::MAX - 1);
+            let cost_from = index2d!(cells,pos).costs[d1].0 - costs.step;
+            assert_ne!(cost_from, u64::MAX - costs.step);
 
             let d1 = DirectionName::from_usize(d1).unwrap();
 
@@ -303,10 +449,7 @@ fn solve_part1_bfs(puzzle: &Puzzle, args: &Args) -> u64
             }
 
             for d2 in [W,E,N,S] {
-                let needed_cost = 
-                    if d1 == d2 { cost_from }
-                    else if d1 == opposite_dir(d2) { cost_from.saturating_sub(2 * 1000) }
-                    else { cost_from.saturating_sub(1000) };
+                let needed_cost = cost_from.saturating_sub(costs.turn(d1, d2));
                 
                 let neighbor_pos = next_pos(pos, d2);
                 let neighbor = &mut index2d!(cells, neighbor_pos);
@@ -331,18 +474,16 @@ fn solve_part1_bfs(puzzle: &Puzzle, args: &Args) -> u64
 
     }
 
-    fn is_on_shortest_path(c: &BfsCellInfo) -> bool { c.on_shortest_path.iter().any(|v| *v) }
-
     if args.debug {
-        for r in 0..rows {
-            for c in 0..cols {
+        for (map_row, cell_row) in puzzle.map.iter().zip(cells.iter()) {
+            for (slot, cell) in map_row.iter().zip(cell_row.iter()) {
                 let ch = 
-                    match puzzle.map[r][c] {
+                    match slot {
                         Start => 'S',
                         End => 'E',
                         Wall => '#',
                         Empty => {
-                            if is_on_shortest_path(&cells[r][c]) { 'O' } else { ' ' }
+                            if is_on_shortest_path(cell) { 'O' } else { ' ' }
                         }
                     };
 
@@ -352,19 +493,18 @@ fn solve_part1_bfs(puzzle: &Puzzle, args: &Args) -> u64
         }
     }
 
-    let path_cell_count = cells.iter().positions2d(is_on_shortest_path).count();
-
-    dbg!(path_cell_count);
-
-    cost_from_start
+    BestPaths { cost: cost_from_start, costs, cells }
 }
 
+// The original recursive solver, kept for reference now that the BFS answers both parts.
+#[allow(dead_code)]
 fn solve_part1(puzzle: &Puzzle, args: &Args) -> usize
 {
     use DirectionName::*;
 
     let rows = puzzle.map.len();
     let cols = puzzle.map[0].len();
+    let costs = args.costs();
 
     let mut cells = vec![vec![CellInfo::default(); cols]; rows];
 
@@ -413,7 +553,8 @@ fn solve_part1(puzzle: &Puzzle, args: &Args) -> usize
         pos: VectorType,
         from_dir: DirectionName,
         visit_stack: &mut Vec<VectorType>,
-        cells: &mut [Vec<CellInfo>]) -> Option<Cost> {
+        cells: &mut [Vec<CellInfo>],
+        costs: &Costs) -> Option<Cost> {
 
         if !(0..puzzle.rows as i64).contains(&pos.x) || !(0..puzzle.cols as i64).contains(&pos.y) {
             return Some(Cost::Wall);
@@ -421,11 +562,11 @@ fn solve_part1(puzzle: &Puzzle, args: &Args) -> usize
 
         match index2d!(cells, pos).visited {
             VisitState::Done{min_costs: _} => {
-                Some(cost_step(pos, &cells, from_dir).0)
+                Some(cost_step(pos, cells, from_dir, costs).0)
             }
 
             VisitState::Started{min_costs:_} => {
-                return Some(Cost::Cycle)
+                Some(Cost::Cycle)
             }
 
             VisitState::New => {
@@ -448,7 +589,7 @@ fn solve_part1(puzzle: &Puzzle, args: &Args) -> usize
         }
     }
 
-    let root_pushed = try_push_node(puzzle, puzzle.start, DirectionName::E, &mut visit_stack, &mut cells);
+    let root_pushed = try_push_node(puzzle, puzzle.start, DirectionName::E, &mut visit_stack, &mut cells, &costs);
     assert_eq!(root_pushed, None);
 
     while let Some(&node_pos) = visit_stack.last() {
@@ -461,7 +602,7 @@ fn solve_part1(puzzle: &Puzzle, args: &Args) -> usize
 
         for t in [N, S, E, W] {
             if min_costs[t as usize].is_none() {
-                min_costs[t as usize] = try_push_node(puzzle, next_pos(node_pos, t), t, &mut visit_stack, &mut cells);
+                min_costs[t as usize] = try_push_node(puzzle, next_pos(node_pos, t), t, &mut visit_stack, &mut cells, &costs);
             }            
         }
 
@@ -490,9 +631,9 @@ fn solve_part1(puzzle: &Puzzle, args: &Args) -> usize
         }
     }
 
-    draw_path(puzzle, &cells);
+    draw_path(puzzle, &follow_costs(puzzle, &cells, &costs));
 
-    let cost = cost_step(puzzle.start, &cells, E).0;
+    let cost = cost_step(puzzle.start, &cells, E, &costs).0;
     match cost {
         Cost::Infinite | Cost::Cycle | Cost::Wall => usize::MAX,
         Cost::Known(c) => c as usize,
@@ -500,9 +641,144 @@ fn solve_part1(puzzle: &Puzzle, args: &Args) -> usize
 
 }
 
-fn solve_part2(puzzle: &Puzzle, args: &Args) -> usize
+fn is_on_shortest_path(c: &BfsCellInfo) -> bool { c.on_shortest_path.iter().any(|v| *v) }
+
+/// Counts the tiles on any best path.
+fn solve_part2(best: &BestPaths) -> usize
 {
-    0
+    best.cells.iter().positions2d(is_on_shortest_path).count()
+}
+
+/// Lists up to `limit` distinct best paths by walking only the moves that keep the
+/// remaining cost optimal. Costs strictly decrease along the way, so there are no cycles.
+fn enumerate_best_paths(puzzle: &Puzzle, best: &BestPaths, limit: usize) -> Vec<Path> {
+    let mut paths = Vec::new();
+    let mut path: Path = vec![(puzzle.start, DirectionName::E)];
+    let mut stack = vec![best.best_moves(puzzle.start, DirectionName::E).collect_vec()];
+
+    while let Some(moves) = stack.last_mut() {
+        let Some(d) = moves.pop() else {
+            stack.pop();
+            path.pop();
+            continue;
+        };
+
+        path.last_mut().unwrap().1 = d;
+        let pos = next_pos(path.last().unwrap().0, d);
+        path.push((pos, d));
+
+        if pos == puzzle.end {
+            paths.push(path.clone());
+            if paths.len() == limit {
+                break;
+            }
+            path.pop();
+        } else {
+            stack.push(best.best_moves(pos, d).collect_vec());
+        }
+    }
+
+    paths
+}
+
+/// Dijkstra over (tile, heading) states from `from` to the end, never entering a tile in
+/// `blocked_tiles` or leaving a tile in a direction listed in `blocked_moves`.
+fn cheapest_path(puzzle: &Puzzle, costs: &Costs, from: (VectorType, DirectionName),
+                 blocked_tiles: &HashSet<VectorType>,
+                 blocked_moves: &HashSet<(VectorType, DirectionName)>) -> Option<Path> {
+    let state = |pos: VectorType, d: DirectionName| (pos.x as usize * puzzle.cols + pos.y as usize) * DIRECTION_COUNT + d as usize;
+
+    let mut dist = vec![u64::MAX; puzzle.rows * puzzle.cols * DIRECTION_COUNT];
+    let mut prev: Vec<Option<(VectorType, DirectionName)>> = vec![None; dist.len()];
+    let mut heap = BinaryHeap::new();
+
+    let (from_pos, from_dir) = from;
+    dist[state(from_pos, from_dir)] = 0;
+    heap.push(Reverse((0, from_pos.x, from_pos.y, from_dir as usize)));
+
+    while let Some(Reverse((cost, x, y, d))) = heap.pop() {
+        let pos = to_vector2(&[x, y]);
+        let direction = DirectionName::from_usize(d).unwrap();
+        if cost > dist[state(pos, direction)] {
+            continue;
+        }
+
+        if pos == puzzle.end {
+            let mut path = vec![(pos, direction)];
+            let mut current = (pos, direction);
+            while let Some((prev_pos, prev_dir)) = prev[state(current.0, current.1)] {
+                path.push((prev_pos, current.1));
+                current = (prev_pos, prev_dir);
+            }
+            path.reverse();
+            return Some(path);
+        }
+
+        for d2 in DIRECTIONS4 {
+            let d2 = DirectionName::from_usize(d2 as usize).unwrap();
+            let next = next_pos(pos, d2);
+            if index2d!(puzzle.map, next) == MapSlot::Wall || blocked_tiles.contains(&next) || blocked_moves.contains(&(pos, d2)) {
+                continue;
+            }
+
+            let next_cost = cost + costs.turn_and_step(direction, d2);
+            if next_cost < dist[state(next, d2)] {
+                dist[state(next, d2)] = next_cost;
+                prev[state(next, d2)] = Some((pos, direction));
+                heap.push(Reverse((next_cost, next.x, next.y, d2 as usize)));
+            }
+        }
+    }
+
+    None
+}
+
+/// Yen's algorithm: the `k` cheapest routes from start to end that differ in the tiles
+/// they visit, cheapest first.
+fn k_shortest_paths(puzzle: &Puzzle, costs: &Costs, k: usize) -> Vec<Path> {
+    let no_tiles = HashSet::new();
+    let no_moves = HashSet::new();
+    let Some(first) = cheapest_path(puzzle, costs, (puzzle.start, DirectionName::E), &no_tiles, &no_moves) else {
+        return Vec::new();
+    };
+
+    let tiles = |path: &Path| path.iter().map(|(pos, _)| *pos).collect_vec();
+    let mut found = vec![first];
+    let mut candidates: Vec<(u64, Path)> = Vec::new();
+
+    while found.len() < k {
+        let last = found.last().unwrap();
+        let last_tiles = tiles(last);
+
+        for spur in 0..last.len() - 1 {
+            let root = &last[..spur];
+            let heading = root.last().map_or(DirectionName::E, |&(_, d)| d);
+
+            // Don't repeat the moves out of the spur tile taken by routes sharing this root.
+            let blocked_moves: HashSet<_> = found.iter()
+                .filter(|p| p.len() > spur + 1 && tiles(p)[..=spur] == last_tiles[..=spur])
+                .map(|p| p[spur])
+                .collect();
+            let blocked_tiles: HashSet<_> = last_tiles[..spur].iter().copied().collect();
+
+            let Some(spur_path) = cheapest_path(puzzle, costs, (last_tiles[spur], heading), &blocked_tiles, &blocked_moves) else {
+                continue;
+            };
+
+            let path = root.iter().copied().chain(spur_path).collect_vec();
+            let path_tiles = tiles(&path);
+            if !candidates.iter().any(|(_, p)| tiles(p) == path_tiles) {
+                candidates.push((path_cost(&path, costs), path));
+            }
+        }
+
+        let Some((i, _)) = candidates.iter().enumerate().min_by_key(|(_, (cost, _))| *cost) else {
+            break;
+        };
+        found.push(candidates.swap_remove(i).1);
+    }
+
+    found
 }
 
 #[derive(Parser, Debug)]
@@ -514,13 +790,31 @@ struct Args {
 
     #[arg(short, long, default_value_t = false)]
     debug: bool,
-}
 
-fn main() -> Result<(), Box<dyn Error>> {
-    let args = Args::parse();
+    /// List up to this many distinct best paths with their turns and draw each one.
+    #[arg(short, long, default_value_t = 0)]
+    paths: usize,
 
-    let str = fs::read_to_string(&args.input_file)?;
+    /// Cost of moving forward one tile.
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u64).range(1..))]
+    step_cost: u64,
+
+    /// Cost of turning 90 degrees.
+    #[arg(long, default_value_t = 1000)]
+    turn_cost: u64,
+
+    /// List the k cheapest distinct routes, whether or not they tie for best.
+    #[arg(short, long, default_value_t = 0)]
+    k_shortest: usize,
+}
 
+impl Args {
+    fn costs(&self) -> Costs {
+        Costs { step: self.step_cost, turn: self.turn_cost }
+    }
+}
+
+fn parse_puzzle(str: &str) -> Result<Puzzle, PuzzleError> {
     let map = str.lines()
         .take_while(|l| {
             !l.trim_ascii().is_empty()
@@ -547,12 +841,39 @@ fn main() -> Result<(), Box<dyn Error>> {
 
     assert!(map.iter().all(|m| m.len() == cols));
 
-    let puzzle = Puzzle{map, start: to_vector2_cast(&start), end: to_vector2_cast(&end), rows, cols};
+    Ok(Puzzle{map, start: to_vector2_cast(&start), end: to_vector2_cast(&end), rows, cols})
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let args = Args::parse();
+
+    let str = fs::read_to_string(&args.input_file)?;
+    let puzzle = parse_puzzle(&str)?;
+
+    let best = solve_part1_bfs(&puzzle, &args);
+    let part1 = best.cost;
+
+    let part2 = solve_part2(&best);
+
+    if args.paths > 0 {
+        for (i, path) in enumerate_best_paths(&puzzle, &best, args.paths).iter().enumerate() {
+            println!("Path {i}: cost {} turns {}", path_cost(path, &best.costs), turn_sequence(path));
+            draw_path(&puzzle, path);
+            println!();
+        }
+    }
+
+    if args.k_shortest > 0 {
+        for (i, path) in k_shortest_paths(&puzzle, &best.costs, args.k_shortest).iter().enumerate() {
+            let cost = path_cost(path, &best.costs);
+            println!("Route {i}: cost {cost} (+{}) turns {}", cost - best.cost, turn_sequence(path));
+            if args.debug {
+                draw_path(&puzzle, path);
+                println!();
+            }
+        }
+    }
 
-    let part1 = solve_part1_bfs(&puzzle, &args);
-    
-    let part2 = solve_part2(&puzzle, &args);
-    
     
     dbg!(part1);
     dbg!(part2);
@@ -1,4 +1,4 @@
-use std::{collections::VecDeque, error::Error, fs, usize};
+use std::{collections::VecDeque, error::Error, fs};
 use clap::Parser;
 use thiserror::Error;
 use neerajsi::*;
@@ -28,6 +28,10 @@ struct Args {
 
     #[arg(short, long, default_value_t = false)]
     debug: bool,
+
+    /// Report whether the exit is reachable, and the shortest path, after this many bytes.
+    #[arg(short, long)]
+    after: Option<usize>,
 }
 
 const DIMENSIONS_SAMPLE: [usize;2] = [7, 7];
@@ -99,6 +103,217 @@ fn solve_part1(wall_list: &[(usize, usize)], dimensions: [usize;2], step_count:
     index2d!(cost_map, start)
 }
 
+/// Disjoint sets over grid cells, with path halving and union by size.
+struct UnionFind {
+    parent: Vec<usize>,
+    size: Vec<usize>,
+}
+
+impl UnionFind {
+    fn new(count: usize) -> Self {
+        UnionFind { parent: (0..count).collect(), size: vec![1; count] }
+    }
+
+    fn find(&mut self, mut i: usize) -> usize {
+        while self.parent[i] != i {
+            self.parent[i] = self.parent[self.parent[i]];
+            i = self.parent[i];
+        }
+        i
+    }
+
+    fn union(&mut self, a: usize, b: usize) {
+        let (mut a, mut b) = (self.find(a), self.find(b));
+        if a == b {
+            return;
+        }
+
+        if self.size[a] < self.size[b] {
+            std::mem::swap(&mut a, &mut b);
+        }
+        self.parent[b] = a;
+        self.size[a] += self.size[b];
+    }
+}
+
+/// The index of the first byte after which the exit can't be reached, or None if it always
+/// can. Starts from the grid with every byte fallen and takes them back out in reverse,
+/// joining each freed cell to its open neighbors; the byte whose removal reconnects start
+/// and exit is the one that cut them apart.
+fn first_blocking_byte(wall_list: &[(usize, usize)], dimensions: [usize; 2]) -> Option<usize> {
+    // A cell is blocked from the first byte that lands on it.
+    let mut fall_time = grid(dimensions, usize::MAX);
+    for (i, &w) in wall_list.iter().enumerate() {
+        let w: [usize; 2] = w.into();
+        let t = &mut index2d!(fall_time, w);
+        *t = (*t).min(i);
+    }
+
+    let cell = |pos: [usize; 2]| pos[0] * dimensions[1] + pos[1];
+    let start = [0, 0];
+    let end = [dimensions[0] - 1, dimensions[1] - 1];
+
+    let mut sets = UnionFind::new(dimensions[0] * dimensions[1]);
+    let mut open = fall_time.iter().map(|r| r.iter().map(|&t| t == usize::MAX).collect_vec()).collect_vec();
+    let join_open_neighbors = |sets: &mut UnionFind, open: &[Vec<bool>], pos: [usize; 2]| {
+        for (n, &is_open) in neighbors_cardinal(open, pos) {
+            if is_open {
+                sets.union(cell(pos), cell(n));
+            }
+        }
+    };
+
+    for r in 0..dimensions[0] {
+        for c in 0..dimensions[1] {
+            if open[r][c] {
+                join_open_neighbors(&mut sets, &open, [r, c]);
+            }
+        }
+    }
+
+    if sets.find(cell(start)) == sets.find(cell(end)) {
+        return None;
+    }
+
+    for (i, &w) in wall_list.iter().enumerate().rev() {
+        let w: [usize; 2] = w.into();
+        if index2d!(fall_time, w) != i {
+            continue;
+        }
+
+        index2d!(open, w) = true;
+        join_open_neighbors(&mut sets, &open, w);
+        if sets.find(cell(start)) == sets.find(cell(end)) {
+            return Some(i);
+        }
+    }
+
+    // Start or exit are covered themselves and nothing else ever separates them.
+    wall_list.iter().position(|&w| [w.0, w.1] == start || [w.0, w.1] == end)
+}
+
+/// A shortest path from start to exit through the open cells, found by BFS.
+fn shortest_path(wall_map: &[Vec<bool>], dimensions: [usize; 2]) -> Option<Vec<[usize; 2]>> {
+    let start = [0, 0];
+    let end = [dimensions[0] - 1, dimensions[1] - 1];
+    if index2d!(wall_map, start) || index2d!(wall_map, end) {
+        return None;
+    }
+
+    let mut came_from = grid(dimensions, None);
+    let mut bfs_queue = VecDeque::from([start]);
+    index2d!(came_from, start) = Some(start);
+
+    while let Some(pos) = bfs_queue.pop_front() {
+        if pos == end {
+            let mut path = vec![end];
+            while let Some(&pos) = path.last().filter(|&&p| p != start) {
+                path.push(index2d!(came_from, pos).unwrap());
+            }
+            path.reverse();
+            return Some(path);
+        }
+
+        for (n, &wall) in neighbors_cardinal(wall_map, pos) {
+            if !wall && index2d!(came_from, n).is_none() {
+                index2d!(came_from, n) = Some(pos);
+                bfs_queue.push_back(n);
+            }
+        }
+    }
+
+    None
+}
+
+/// Lets bytes fall one at a time while keeping a shortest path from start to exit. A byte
+/// that misses the current path can't make it longer, so the grid is only searched again
+/// when one lands on it.
+struct FallingBytes<'a> {
+    wall_list: &'a [(usize, usize)],
+    dimensions: [usize; 2],
+    wall_map: Vec<Vec<bool>>,
+    on_path: Vec<Vec<bool>>,
+    path: Option<Vec<[usize; 2]>>,
+    fallen: usize,
+    searches: usize,
+}
+
+impl<'a> FallingBytes<'a> {
+    fn new(wall_list: &'a [(usize, usize)], dimensions: [usize; 2]) -> Self {
+        let mut bytes = FallingBytes {
+            wall_list,
+            dimensions,
+            wall_map: grid(dimensions, false),
+            on_path: grid(dimensions, false),
+            path: None,
+            fallen: 0,
+            searches: 0,
+        };
+        bytes.search();
+        bytes
+    }
+
+    fn search(&mut self) {
+        if let Some(path) = &self.path {
+            for &p in path.iter() {
+                index2d!(self.on_path, p) = false;
+            }
+        }
+
+        self.path = shortest_path(&self.wall_map, self.dimensions);
+        self.searches += 1;
+
+        if let Some(path) = &self.path {
+            for &p in path.iter() {
+                index2d!(self.on_path, p) = true;
+            }
+        }
+    }
+
+    /// Lets bytes fall until `count` have landed. Going back replays from an empty grid.
+    fn advance_to(&mut self, count: usize) {
+        if count < self.fallen {
+            *self = FallingBytes::new(self.wall_list, self.dimensions);
+        }
+
+        for &w in self.wall_list[self.fallen..count.min(self.wall_list.len())].iter() {
+            let w: [usize; 2] = w.into();
+            index2d!(self.wall_map, w) = true;
+            self.fallen += 1;
+
+            if self.path.is_some() && index2d!(self.on_path, w) {
+                self.search();
+            }
+        }
+    }
+
+    fn is_reachable(&self) -> bool {
+        self.path.is_some()
+    }
+
+    /// The number of steps on the current shortest path.
+    fn path_len(&self) -> Option<usize> {
+        self.path.as_ref().map(|p| p.len() - 1)
+    }
+
+    fn draw(&self) {
+        for r in 0..self.dimensions[0] {
+            for c in 0..self.dimensions[1] {
+                let pos = [r, c];
+                if index2d!(self.wall_map, pos) {
+                    print!("#");
+                } else if index2d!(self.on_path, pos) {
+                    print!("O");
+                } else {
+                    print!(".");
+                }
+            }
+            println!();
+        }
+        println!();
+    }
+}
+
 fn main() -> Result<(), Box<dyn Error>> {
     let args = Args::parse();
 
@@ -106,7 +321,7 @@ fn main() -> Result<(), Box<dyn Error>> {
 
     let wall_list: Vec<(usize, usize)> = str.lines()
         .map(|l| {
-            scan_fmt!(l, "{},{}", usize, usize)
+            scan_fmt!(l, "{},{}", usize, usize).map_err(|e| PuzzleError::ParseError(format!("{l}: {e}")))
         })
         .try_collect()?;
 
@@ -116,16 +331,106 @@ fn main() -> Result<(), Box<dyn Error>> {
 
     dbg!(part1); 
 
-    if !args.debug{
-        let indexes = (0..wall_list.len()).collect_vec();
-    
-        let index = indexes.partition_point(|&i| {
-            solve_part1(&wall_list, dimensions, i, &args) != usize::MAX
-        });
-    
+    if let Some(index) = first_blocking_byte(&wall_list, dimensions) {
         dbg!(index);
-        dbg!(wall_list[index - 1]);
+        dbg!(wall_list[index]);
+    } else {
+        println!("The exit is never cut off");
+    }
+
+    if let Some(after) = args.after {
+        let mut bytes = FallingBytes::new(&wall_list, dimensions);
+        bytes.advance_to(after);
+        match bytes.path_len() {
+            Some(len) => println!("After {after} bytes the exit is reachable in {len} steps"),
+            None => println!("After {after} bytes the exit is unreachable"),
+        }
+
+        if args.debug && bytes.is_reachable() {
+            bytes.draw();
+        }
     }
 
     Ok(())
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The original search: binary search over the number of fallen bytes, with a full BFS
+    /// for each probe.
+    fn first_blocking_byte_bisect(wall_list: &[(usize, usize)], dimensions: [usize; 2], args: &Args) -> Option<usize> {
+        let indexes = (0..=wall_list.len()).collect_vec();
+        let index = indexes.partition_point(|&i| {
+            solve_part1(wall_list, dimensions, i, args) != usize::MAX
+        });
+
+        (index <= wall_list.len()).then(|| index - 1)
+    }
+
+    fn random_walls(seed: u64, count: usize, dimensions: [usize; 2]) -> Vec<(usize, usize)> {
+        let mut state = seed;
+        let mut next = |n: usize| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            (state % n as u64) as usize
+        };
+
+        // The original search starts from the exit even when it's covered, so keep bytes off
+        // the start and exit when comparing against it.
+        let corners = [(0, 0), (dimensions[0] - 1, dimensions[1] - 1)];
+        std::iter::repeat_with(|| (next(dimensions[0]), next(dimensions[1])))
+            .filter(|w| !corners.contains(w))
+            .take(count)
+            .collect()
+    }
+
+    #[test]
+    fn sample() {
+        let args = Args::parse_from(["day18"]);
+        let wall_list: Vec<(usize, usize)> = include_str!("../input_sample.txt").lines()
+            .map(|l| scan_fmt!(l, "{},{}", usize, usize).unwrap())
+            .collect();
+
+        assert_eq!(solve_part1(&wall_list, DIMENSIONS_SAMPLE, 12, &args), 22);
+        assert_eq!(first_blocking_byte(&wall_list, DIMENSIONS_SAMPLE), Some(20));
+        assert_eq!(wall_list[20], (6, 1));
+        assert_eq!(first_blocking_byte_bisect(&wall_list, DIMENSIONS_SAMPLE, &args), Some(20));
+    }
+
+    #[test]
+    fn union_find_matches_bisect() {
+        let args = Args::parse_from(["day18"]);
+        let dimensions = [15, 15];
+        for seed in 1..40 {
+            // Repeats are allowed here.
+            let wall_list = random_walls(seed, 120, dimensions);
+            assert_eq!(first_blocking_byte(&wall_list, dimensions),
+                       first_blocking_byte_bisect(&wall_list, dimensions, &args), "seed {seed}");
+        }
+
+        let mut wall_list = random_walls(1, 120, dimensions);
+        wall_list.insert(5, (14, 14));
+        assert_eq!(first_blocking_byte(&wall_list, dimensions), Some(5));
+    }
+
+    #[test]
+    fn falling_bytes_matches_full_search() {
+        let args = Args::parse_from(["day18"]);
+        let dimensions = [12, 12];
+        let wall_list = random_walls(7, 80, dimensions);
+
+        let mut bytes = FallingBytes::new(&wall_list, dimensions);
+        for k in 0..=wall_list.len() {
+            bytes.advance_to(k);
+            let expected = solve_part1(&wall_list, dimensions, k, &args);
+            assert_eq!(bytes.path_len().unwrap_or(usize::MAX), expected, "after {k}");
+        }
+        assert!(bytes.searches < wall_list.len());
+
+        bytes.advance_to(3);
+        assert_eq!(bytes.path_len(), Some(solve_part1(&wall_list, dimensions, 3, &args)));
+    }
+}
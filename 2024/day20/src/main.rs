@@ -1,11 +1,12 @@
 use arrayvec::ArrayVec;
-use clap::Parser;
+use clap::{Parser, ValueEnum};
 use itertools::Itertools;
 use neerajsi::Iterable2d;
 use neerajsi::*;
 use num_derive::FromPrimitive;
 use num_traits::FromPrimitive;
 use std::collections::BTreeMap;
+use std::collections::VecDeque;
 use std::error::Error;
 use std::fs;
 use thiserror::Error;
@@ -14,6 +15,9 @@ use thiserror::Error;
 enum PuzzleError {
     #[error("Parsing error: {0}")]
     ParseError(String),
+
+    #[error("The end can't be reached from the start")]
+    NoPath,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, FromPrimitive)]
@@ -34,60 +38,149 @@ struct Puzzle {
 
 use neerajsi::CardinalDirectionName::*;
 
-fn solve_part1_again(puzzle: &Puzzle, cutoff: usize, max_td: usize, args: &Args, _timings: &mut TimingBuffer) -> usize {
+type DistanceMap = Vec<Vec<Option<usize>>>;
+
+/// Steps from `from` to every open cell it can reach.
+fn bfs_distances(puzzle: &Puzzle, from: Location) -> DistanceMap {
     let grid = Grid::new(puzzle.rows, puzzle.cols);
+    let mut dist = vec2d!(puzzle.rows, puzzle.cols, None);
+    let mut bfs_queue = VecDeque::from([from]);
+    index2d_array!(dist, from) = Some(0usize);
+
+    while let Some(pos) = bfs_queue.pop_front() {
+        let d = index2d_array!(dist, pos).unwrap();
+        for next_pos in grid.neighbors_iter_cardinal(pos, &[W, E, N, S]) {
+            if index2d_array!(puzzle.map, next_pos) != MapSlot::Wall && index2d_array!(dist, next_pos).is_none() {
+                index2d_array!(dist, next_pos) = Some(d + 1);
+                bfs_queue.push_back(next_pos);
+            }
+        }
+    }
 
-    let mut costs = vec2d!(puzzle.rows, puzzle.cols, None);
+    dist
+}
 
-    let mut track = Vec::new();
-    let mut pos = puzzle.start;
-    let mut cost = 0usize;
-    loop {
-        index2d_array!(costs, pos).replace(cost);
-        cost += 1;
+/// Shortest times from the start to each open cell and from each open cell to the end. Moves
+/// are reversible, so the second is a search out from the end.
+struct Distances {
+    from_start: DistanceMap,
+    to_end: DistanceMap,
+    /// Time of the fastest race without cheating.
+    best: usize,
+}
 
-        track.push(pos);
-        if pos == puzzle.end {
-            break;
-        }
+impl Distances {
+    fn new(puzzle: &Puzzle) -> Result<Self, PuzzleError> {
+        let from_start = bfs_distances(puzzle, puzzle.start);
+        let best = index2d_array!(from_start, puzzle.end).ok_or(PuzzleError::NoPath)?;
+        let to_end = bfs_distances(puzzle, puzzle.end);
+        Ok(Distances { from_start, to_end, best })
+    }
 
-        pos = [W, E, N, S]
-            .iter()
-            .filter_map(|&d| {
-                let next_pos = grid.add_cardinal(pos, d).unwrap();
-                if Some(&next_pos) == track.iter().nth_back(1) {
-                    return None;
-                }
-                if index2d_array!(puzzle.map, next_pos) != MapSlot::Wall {
-                    return Some(next_pos);
-                }
+    /// Time saved by racing to `a`, cheating `td` steps to `b` and racing on to the end.
+    fn savings(&self, a: Location, b: Location, td: usize) -> Option<usize> {
+        let time = index2d_array!(self.from_start, a)? + td + index2d_array!(self.to_end, b)?;
+        self.best.checked_sub(time).filter(|&s| s > 0)
+    }
+}
 
-                None
-            })
-            .exactly_one()
+/// One shortest path from the start to the end. Open cells off it, like dead ends and side
+/// branches, aren't part of the race, though cheats can still pass over them.
+fn race_track(puzzle: &Puzzle, distances: &Distances) -> Vec<Location> {
+    let grid = Grid::new(puzzle.rows, puzzle.cols);
+    let dist = &distances.from_start;
+    let mut pos = puzzle.end;
+    let mut d = distances.best;
+    let mut track = vec![pos];
+    while d > 0 {
+        d -= 1;
+        pos = grid.neighbors_iter_cardinal(pos, &[W, E, N, S])
+            .find(|&p| index2d_array!(dist, p) == Some(d))
             .unwrap();
+        track.push(pos);
+    }
+
+    track.reverse();
+    track
+}
+
+/// Time to reach each track cell, or `None` off the track.
+fn track_costs(puzzle: &Puzzle, track: &[Location]) -> Vec<Vec<Option<usize>>> {
+    let mut costs = vec2d!(puzzle.rows, puzzle.cols, None);
+    for (cost, &pos) in track.iter().enumerate() {
+        index2d_array!(costs, pos) = Some(cost);
     }
 
-    let len = track.len();
+    costs
+}
+
+type Cheats = BTreeMap<usize, Vec<(Location, Location)>>;
+
+/// How cheats are searched for. Either way a cheat can start on any open cell reachable from
+/// the start and end on any open cell the end is reachable from, so forks and loops in the
+/// maze are fine.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Method {
+    /// Every pair of open cells, O(cells²).
+    Pairs,
+    /// Every cell within reach of each open cell, O(cells × max_td²).
+    Scan,
+}
 
-    let mut cheats: BTreeMap<usize, Vec<(Location, Location)>> = BTreeMap::new();
+fn open_cells(distances: &DistanceMap) -> Vec<Location> {
+    distances.iter().positions2d(|d| d.is_some()).map(|l| l.into()).collect()
+}
+
+fn cheats_pairwise(distances: &Distances, cutoff: usize, max_td: usize) -> Cheats {
+    let starts = open_cells(&distances.from_start);
+    let ends = open_cells(&distances.to_end);
 
-    for i in 0..(len - 2) {
-        for j in (i + 2)..len {
-            let (a, b) = (track[i], track[j]);
+    let mut cheats = Cheats::new();
+
+    for &a in &starts {
+        for &b in &ends {
             let td = taxicab_distance(a, b);
-            let orig_cost = j - i;
-            if orig_cost <= td {
+            if td > max_td {
                 continue;
             }
 
-            let savings = orig_cost - td;
-            if td <= max_td && savings >= cutoff {
+            if let Some(savings) = distances.savings(a, b, td).filter(|&s| s >= cutoff) {
                 cheats.entry(savings).or_default().push((a, b));
             }
         }
     }
 
+    cheats
+}
+
+fn cheats_scan(puzzle: &Puzzle, distances: &Distances, cutoff: usize, max_td: usize) -> Cheats {
+    let grid = Grid::new(puzzle.rows, puzzle.cols);
+    let max_td = max_td as i64;
+
+    let mut cheats = Cheats::new();
+    for a in open_cells(&distances.from_start) {
+        for dr in -max_td..=max_td {
+            let reach = max_td - dr.abs();
+            for dc in -reach..=reach {
+                let Some(b) = grid.add_direction(a, [dr, dc]) else { continue };
+
+                let td = (dr.abs() + dc.abs()) as usize;
+                if let Some(savings) = distances.savings(a, b, td).filter(|&s| s >= cutoff) {
+                    cheats.entry(savings).or_default().push((a, b));
+                }
+            }
+        }
+    }
+
+    cheats
+}
+
+fn solve_part1_again(puzzle: &Puzzle, distances: &Distances, cutoff: usize, max_td: usize, args: &Args, _timings: &mut TimingBuffer) -> Cheats {
+    let cheats = match args.method {
+        Method::Pairs => cheats_pairwise(distances, cutoff, max_td),
+        Method::Scan => cheats_scan(puzzle, distances, cutoff, max_td),
+    };
+
     if args.debug {
         for (savings, cheats) in cheats.iter() {
             let count = cheats.len();
@@ -96,46 +189,17 @@ fn solve_part1_again(puzzle: &Puzzle, cutoff: usize, max_td: usize, args: &Args,
         }
     }
 
-    cheats.values().map(|v| v.len()).sum()
+    cheats
 }
 
-fn solve_part1(puzzle: &Puzzle, args: &Args, _timings: &mut TimingBuffer) -> usize {
-    let rows = puzzle.rows;
+fn solve_part1(puzzle: &Puzzle, track: &[Location], args: &Args, _timings: &mut TimingBuffer) -> usize {
     let cols = puzzle.cols;
-    let grid = Grid::new(rows, cols);
-
-    let mut costs = vec2d!(puzzle.rows, puzzle.cols, None);
-
-    let mut pos = puzzle.start;
-    let mut cost = 0usize;
-    let mut from_dir = W;
-    loop {
-        index2d_array!(costs, pos).replace(cost);
-        cost += 1;
 
-        if pos == puzzle.end {
-            break;
-        }
-
-        (pos, from_dir) = [W, E, N, S]
-            .iter()
-            .filter_map(|&d| {
-                if d != from_dir {
-                    let next_pos = grid.add_cardinal(pos, d).unwrap();
-                    if index2d_array!(puzzle.map, next_pos) != MapSlot::Wall {
-                        return Some((next_pos, opposite_dir_cardinal(d)));
-                    }
-                }
-
-                None
-            })
-            .exactly_one()
-            .unwrap();
-    }
+    let costs = track_costs(puzzle, track);
 
     let mut cheats: BTreeMap<usize, Vec<(Location, Location)>> = BTreeMap::new();
 
-    for wall in costs.iter().positions2d(|c| *c == None) {
+    for wall in costs.iter().positions2d(|c| c.is_none()) {
         type CostAndLoc = (usize, Location);
 
         let mut dirs: ArrayVec<CostAndLoc, 4> = neighbors_cardinal(&costs, wall.into())
@@ -199,10 +263,10 @@ fn solve_part1(puzzle: &Puzzle, args: &Args, _timings: &mut TimingBuffer) -> usi
             print!("{c:4}|");
         }
         println!();
-        for r in 0..rows {
+        for (r, row) in costs.iter().enumerate() {
             print!("{r:4}|");
-            for c in 0..cols {
-                if let Some(cost) = costs[r][c] {
+            for cost in row {
+                if let Some(cost) = cost {
                     print!("{cost:4},");
                 } else {
                     print!("****,");
@@ -232,6 +296,13 @@ struct Args {
     #[arg(short, long, default_value_t = 2)]
     max_td: usize,
 
+    /// How to search for cheats.
+    #[arg(long, value_enum, default_value_t = Method::Scan)]
+    method: Method,
+
+    /// Print a savings,count CSV of the cheats.
+    #[arg(long, default_value_t = false)]
+    histogram: bool,
 }
 
 fn main() -> Result<(), Box<dyn Error>> {
@@ -273,7 +344,7 @@ fn main() -> Result<(), Box<dyn Error>> {
 
     let puzzle = Puzzle {
         map,
-        start: start,
+        start,
         end,
         rows,
         cols,
@@ -281,12 +352,105 @@ fn main() -> Result<(), Box<dyn Error>> {
 
     let mut timings = TimingBuffer::new();
 
-    let part1 = time_it("part1", || solve_part1(&puzzle, &args, &mut timings));
+    let distances = Distances::new(&puzzle)?;
+    let track = race_track(&puzzle, &distances);
+
+    let part1 = time_it("part1", || solve_part1(&puzzle, &track, &args, &mut timings));
 
     dbg!(part1);
 
-    let part1_again = time_it("part1_again", || solve_part1_again(&puzzle, args.cutoff, args.max_td, &args, &mut timings));
+    let cheats = time_it("part1_again", || solve_part1_again(&puzzle, &distances, args.cutoff, args.max_td, &args, &mut timings));
+    let part1_again: usize = cheats.values().map(|v| v.len()).sum();
     dbg!(part1_again);
 
+    if args.histogram {
+        println!("savings,count");
+        for (savings, cheats) in &cheats {
+            println!("{savings},{}", cheats.len());
+        }
+    }
+
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(text: &str) -> Puzzle {
+        let map = text.lines()
+            .map(|l| l.bytes().map(|c| MapSlot::from_u8(c).unwrap()).collect_vec())
+            .collect_vec();
+        let start = map.iter().positions2d(|v| *v == MapSlot::Start).exactly_one().unwrap().into();
+        let end = map.iter().positions2d(|v| *v == MapSlot::End).exactly_one().unwrap().into();
+        Puzzle { rows: map.len(), cols: map[0].len(), map, start, end }
+    }
+
+    fn histogram(cheats: &Cheats) -> Vec<(usize, usize)> {
+        cheats.iter().map(|(&savings, c)| (savings, c.len())).collect()
+    }
+
+    #[test]
+    fn sample() {
+        let puzzle = parse(include_str!("../input_sample.txt"));
+        let distances = Distances::new(&puzzle).unwrap();
+        assert_eq!(race_track(&puzzle, &distances).len(), 85);
+
+        let short = cheats_scan(&puzzle, &distances, 1, 2);
+        assert_eq!(histogram(&short), [(2, 14), (4, 14), (6, 2), (8, 4), (10, 2), (12, 3), (20, 1), (36, 1), (38, 1), (40, 1), (64, 1)]);
+
+        let long = cheats_scan(&puzzle, &distances, 50, 20);
+        assert_eq!(histogram(&long).iter().map(|h| h.1).sum::<usize>(), 285);
+        assert_eq!(histogram(&long)[0], (50, 32));
+
+        for (cutoff, max_td) in [(1, 2), (50, 20), (1, 5)] {
+            assert_eq!(histogram(&cheats_scan(&puzzle, &distances, cutoff, max_td)), histogram(&cheats_pairwise(&distances, cutoff, max_td)));
+        }
+    }
+
+    #[test]
+    fn track_with_branches() {
+        // Dead ends below the start and beside the long corridor.
+        let text = include_str!("../input_sample.txt")
+            .lines()
+            .enumerate()
+            .map(|(r, l)| match r {
+                4 => l.replacen("##", "#.", 1),
+                5 => l.replacen("#.", "..", 1),
+                _ => l.to_string(),
+            })
+            .join("\n");
+
+        let puzzle = parse(&text);
+        let distances = Distances::new(&puzzle).unwrap();
+        assert_eq!(race_track(&puzzle, &distances).len(), 85);
+
+        // Cheats that pass through the dead ends come on top of the ones along the corridor.
+        let sample = parse(include_str!("../input_sample.txt"));
+        let corridor = cheats_scan(&sample, &Distances::new(&sample).unwrap(), 1, 2);
+        let branched = cheats_scan(&puzzle, &distances, 1, 2);
+        assert!(corridor.iter().all(|(savings, c)| c.iter().all(|pair| branched[savings].contains(pair))));
+        assert_eq!(histogram(&branched), histogram(&cheats_pairwise(&distances, 1, 2)));
+    }
+
+    #[test]
+    fn maze() {
+        // The way right is blocked, so the race goes around the bottom, but a cheat can reach
+        // the dead end next to the end.
+        let puzzle = parse(concat!(
+            "#######\n",
+            "#S.#.E#\n",
+            "#.###.#\n",
+            "#.....#\n",
+            "#######\n"));
+
+        let distances = Distances::new(&puzzle).unwrap();
+        assert_eq!(distances.best, 8);
+
+        let cheats = cheats_scan(&puzzle, &distances, 1, 2);
+        assert_eq!(cheats.last_key_value(), Some((&4, &vec![([1, 2], [1, 4])])));
+        for max_td in 2..6 {
+            assert_eq!(histogram(&cheats_scan(&puzzle, &distances, 1, max_td)), histogram(&cheats_pairwise(&distances, 1, max_td)));
+        }
+    }
+}
@@ -1,12 +1,13 @@
-use std::{clone, collections::{btree_set, BTreeMap, HashMap, HashSet}, hash::Hash, path::Display, str::FromStr, time::Instant};
+use std::{collections::{BTreeMap, BTreeSet, HashMap, HashSet}, str::FromStr};
 
-use clap::Parser;
+use clap::{Parser, ValueEnum};
 use itertools::Itertools;
-use petgraph::algo::bellman_ford;
+use netlist::{bus_bit, GateOp, Netlist, NetlistError};
 use scan_fmt::scan_fmt;
 use strum::EnumString;
 
 #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, EnumString)]
+#[allow(clippy::upper_case_acronyms)]
 enum OPERATOR {
     XOR,
     OR,
@@ -24,6 +25,50 @@ struct Args {
 
     #[arg(short, long, default_value_t = false)]
     debug: bool,
+
+    /// Find the swapped output wires automatically instead of printing the renamed circuit.
+    #[arg(short, long, default_value_t = false)]
+    find_swaps: bool,
+
+    /// Print the circuit as a Graphviz DOT graph labeled with the inferred wire roles.
+    #[arg(long, default_value_t = false)]
+    dot: bool,
+
+    /// Maximum number of wire pairs the swap finder may exchange.
+    #[arg(long, default_value_t = 4)]
+    max_swaps: usize,
+
+    /// The operation the circuit is checked against.
+    #[arg(short, long, value_enum, default_value_t = Spec::Adder)]
+    spec: Spec,
+}
+
+/// The operations a circuit can be checked against. Results are truncated to the width
+/// of the z bus.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, strum::Display)]
+#[strum(serialize_all = "lowercase")]
+enum Spec {
+    Adder,
+    Subtractor,
+    And,
+}
+
+impl Spec {
+    fn expected(self, x: u64, y: u64) -> u64 {
+        match self {
+            Spec::Adder => x.wrapping_add(y),
+            Spec::Subtractor => x.wrapping_sub(y),
+            Spec::And => x & y,
+        }
+    }
+
+    fn symbol(self) -> &'static str {
+        match self {
+            Spec::Adder => "+",
+            Spec::Subtractor => "-",
+            Spec::And => "&",
+        }
+    }
 }
 
 type ExpressionMap = HashMap<String, (OPERATOR, String, String)>;
@@ -42,7 +87,7 @@ enum Operand {
     Original(String),
 }
 
-impl <'a> std::fmt::Display for Operand {
+impl std::fmt::Display for Operand {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         use Operand::*;
         match self {
@@ -58,132 +103,227 @@ impl <'a> std::fmt::Display for Operand {
     }
 }
 
-fn main() {
-    let args = Args::parse();
+fn wire_name(prefix: char, bit: usize) -> String {
+    format!("{prefix}{bit:02}")
+}
 
-    let start = Instant::now();
+/// The input width, taken from the wider of the x and y buses.
+fn input_width(netlist: &Netlist) -> usize {
+    netlist.bus('x').len().max(netlist.bus('y').len())
+}
 
-    let mut swaps: HashMap<&str, &str> = HashMap::new();
+/// A mask of the low `width` bits.
+fn low_bits(width: usize) -> u64 {
+    if width == 0 { 0 } else { u64::MAX >> (64 - width.min(64)) }
+}
 
-    if !args.swaps.is_empty() {
-        for (a, b) in args.swaps.split(',').tuples() {
-            swaps.insert(a, b);
-            swaps.insert(b, a);
+fn build_netlist(expressions: &ExpressionMap) -> Result<Netlist, NetlistError> {
+    Netlist::new(expressions.iter().map(|(out, (op, a, b))| {
+        let op = match op {
+            OPERATOR::AND => GateOp::And,
+            OPERATOR::OR => GateOp::Or,
+            OPERATOR::XOR => GateOp::Xor,
+        };
+        (out.as_str(), op, a.as_str(), b.as_str())
+    }))
+}
+
+/// Bit-walking vectors that exercise each column and each carry, plus some pseudo-random ones.
+fn test_vectors(width: usize) -> Vec<(u64, u64)> {
+    let mask = low_bits(width);
+    let mut vectors = vec![(mask, mask), (mask, 1), (0, 0)];
+    for i in 0..width {
+        let bit = 1u64 << i;
+        vectors.extend([(bit, 0), (0, bit), (bit, bit), (bit - 1, 1), (mask, bit)]);
+    }
+
+    let mut state = 0x2545_f491_4f6c_dd1du64;
+    let mut next = || {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        state & mask
+    };
+    for _ in 0..64 {
+        vectors.push((next(), next()));
+    }
+
+    vectors
+}
+
+/// Bits of z that disagree with the spec, one entry per vector.
+fn wrong_bits(netlist: &Netlist, spec: Spec, vectors: &[(u64, u64)]) -> Vec<u64> {
+    let mask = low_bits(netlist.bus('z').len());
+    vectors.iter()
+        .zip(netlist.evaluate_many(vectors))
+        .map(|(&(x, y), z)| (z ^ spec.expected(x, y)) & mask)
+        .collect()
+}
+
+/// The lowest z bit that is wrong for any vector, or None if the circuit meets the spec.
+fn lowest_failing_bit(netlist: &Netlist, spec: Spec, vectors: &[(u64, u64)]) -> Option<usize> {
+    wrong_bits(netlist, spec, vectors).into_iter()
+        .filter(|&w| w != 0)
+        .map(|w| w.trailing_zeros() as usize)
+        .min()
+}
+
+/// Whether every vector gets bits `0..=bit` of the result right, checking 64 vectors at a time.
+fn correct_through(netlist: &Netlist, spec: Spec, vectors: &[(u64, u64)], bit: usize) -> bool {
+    let mask = low_bits(bit + 1);
+    vectors.chunks(64).all(|chunk| wrong_bits(netlist, spec, chunk).iter().all(|w| w & mask == 0))
+}
+
+/// Checks every gate against the ripple-carry full-adder shape:
+///      Zn = (Xn ^ Yn) ^ Cn-1
+///      Cn = (Xn & Yn) | ((Xn ^ Yn) & Cn-1)
+/// and returns the gate outputs that don't fit, with the reason.
+fn structural_suspects(expressions: &ExpressionMap, width: usize) -> BTreeMap<String, &'static str> {
+    let is_input = |w: &str| bus_bit(w, 'x').is_some() || bus_bit(w, 'y').is_some();
+    let is_first_input = |w: &str| w == "x00" || w == "y00";
+    let is_z = |w: &str| bus_bit(w, 'z').is_some();
+    let top_z = wire_name('z', width);
+
+    let feeds = |wire: &str, op: OPERATOR| expressions.values()
+        .any(|(o, a, b)| *o == op && (a == wire || b == wire));
+
+    let mut suspects = BTreeMap::new();
+    for (out, (op, a, _)) in expressions.iter() {
+        let reason = match op {
+            _ if *out == top_z => (*op != OPERATOR::OR).then_some("final carry is not an OR"),
+            OPERATOR::XOR if is_z(out) =>
+                (is_input(a) && !is_first_input(a)).then_some("sum bit skips the carry"),
+            _ if is_z(out) => Some("sum bit is not an XOR"),
+            OPERATOR::XOR if !is_input(a) => Some("carry XOR does not drive a sum bit"),
+            OPERATOR::XOR => (!feeds(out, OPERATOR::XOR)).then_some("half sum does not feed a sum XOR"),
+            OPERATOR::AND if is_first_input(a) => (!feeds(out, OPERATOR::XOR)).then_some("first carry does not feed a sum XOR"),
+            OPERATOR::AND => (!feeds(out, OPERATOR::OR)).then_some("carry term does not feed a carry OR"),
+            OPERATOR::OR => (!feeds(out, OPERATOR::XOR)).then_some("carry does not feed a sum XOR"),
+        };
+
+        if let Some(reason) = reason {
+            suspects.insert(out.clone(), reason);
         }
     }
 
-    let str = std::fs::read_to_string(&args.input_file).expect("Could not read input");
+    suspects
+}
 
-    let mut lines = str.lines();
+/// Pairs up suspect wires, trying every matching of up to `max_pairs` pairs.
+fn match_suspects(netlist: &mut Netlist, vectors: &[(u64, u64)], suspects: &[String],
+                  max_pairs: usize, swaps: &mut Vec<(String, String)>) -> bool {
+    let Some((first, rest)) = suspects.split_first() else {
+        return correct_through(netlist, Spec::Adder, vectors, input_width(netlist));
+    };
 
-    let values: ValueMap = lines.by_ref()
-        .take_while(|l| !l.is_empty())
-        .map(|l| scan_fmt!(l, "{}: {d}", String, u8).expect("Parse error"))
-        .map(|(s, v)| (s, v != 0))
-        .collect();
+    if swaps.len() < max_pairs {
+        for (i, other) in rest.iter().enumerate() {
+            if netlist.swap_outputs(first, other).is_err() {
+                continue;
+            }
+            swaps.push((first.clone(), other.clone()));
 
-    let expressions_original = lines
-        .map(|l|  {
-            let res = scan_fmt!(l, "{} {} {} -> {}", String, String, String, String);
-            if res.is_err() {
-                panic!("Could not parse {l}: {res:?}");
+            let remaining = rest.iter().enumerate().filter(|(j, _)| *j != i).map(|(_, w)| w.clone()).collect_vec();
+            if match_suspects(netlist, vectors, &remaining, max_pairs, swaps) {
+                return true;
             }
 
-            let (a, op, b, out) = res.unwrap();
-            
-            let result = if let Some(s) = swaps.get(out.as_str()) {
-                s.to_string()
-            } else {
-                out
-            };
+            swaps.pop();
+            netlist.swap_outputs(first, other).unwrap();
+        }
+    }
 
-            let (a, b) = if a < b { (a, b) } else { (b, a) };
-            
-            (result, (OPERATOR::from_str(&op).unwrap(), a, b))
-        }).collect_vec();
+    // The rules can flag a correct wire next to a broken one, so also try leaving it alone.
+    match_suspects(netlist, vectors, rest, max_pairs, swaps)
+}
 
-    let expressions: ExpressionMap = expressions_original.iter()
-        .map(|v| v.clone() )
-        .collect();
-        
-    let mut solved = values.clone();
+/// Fixes the lowest failing bit one swap at a time. One side of each swap comes from the
+/// logic feeding the failing bit that isn't already trusted by the correct bits below it.
+fn search_by_bit(netlist: &mut Netlist, spec: Spec, vectors: &[(u64, u64)], max_pairs: usize,
+                 swaps: &mut Vec<(String, String)>) -> bool {
+    let Some(bit) = lowest_failing_bit(netlist, spec, vectors) else {
+        return true;
+    };
 
-    fn solve_recurse(search: &str, expressions: &ExpressionMap, values: &ValueMap) -> Option<bool> {
-        if let Some(&value) = values.get(search) {
-            return Some(value);
-        }
+    if swaps.len() == max_pairs {
+        return false;
+    }
 
-        let (op, left, right) = expressions.get(search)?;
-        let left = solve_recurse(left, expressions, values);
-        let right = solve_recurse(right, expressions, values);
+    let z = netlist.bus('z');
+    let trusted: HashSet<usize> = z[..bit].iter().flat_map(|&w| netlist.fan_in(w)).collect();
+    let local: HashSet<usize> = z[bit..(bit + 2).min(z.len())].iter().flat_map(|&w| netlist.fan_in(w)).collect();
 
-        let (a, b) = if left.is_some() { (left, right) } else { (right, left) };
+    let names = |wires: &mut dyn Iterator<Item = &usize>| wires.map(|&w| netlist.name(w).to_string()).sorted().collect_vec();
+    let local = names(&mut local.difference(&trusted));
+    let others = names(&mut netlist.gates().iter().map(|g| &g.out).filter(|w| !trusted.contains(*w)));
 
-        match op {
-            OPERATOR::AND => {
-                if let Some(a) = a {
-                    if !a { Some(false) } else { b }
-                } else {
-                    assert_eq!(b, None);
-                    None
-                }
-            }
-            OPERATOR::OR => {
-                if let Some(a) = a {
-                    if a { Some(true) } else { b }
-                } else {
-                    assert_eq!(b, None);
-                    None
-                }
+    for a in local.iter() {
+        for b in others.iter().filter(|b| *b != a) {
+            if netlist.swap_outputs(a, b).is_err() {
+                continue;
             }
-            OPERATOR::XOR => {
-                if let (Some(a), Some(b)) = (a, b) {
-                    Some(a ^ b)
-                } else {
-                    None
+
+            if correct_through(netlist, spec, vectors, bit) {
+                swaps.push((a.clone(), b.clone()));
+                if search_by_bit(netlist, spec, vectors, max_pairs, swaps) {
+                    return true;
                 }
+                swaps.pop();
             }
+            netlist.swap_outputs(a, b).unwrap();
         }
     }
 
-    let mut z = 0;
-    for i in 0..64 {
-        let z_wire = format!("z{i:02}");
-
-        let val = solve_recurse(&z_wire, &expressions, &mut solved);
-        
-        if args.debug {
-            println!("{z_wire} = {val:?}");
-        }
-        
-        let Some(val) = val else { break };
+    false
+}
 
-        z |= (val as usize ) << (i as usize);
+/// Finds up to `max_pairs` output swaps that make the circuit meet the spec, returning the
+/// sorted list of swapped wires. The structural rules only apply to adders; other specs go
+/// straight to the bit-by-bit search.
+fn find_swaps(expressions: &ExpressionMap, spec: Spec, max_pairs: usize, debug: bool) -> Result<Option<Vec<String>>, NetlistError> {
+    let mut netlist = build_netlist(expressions)?;
+    let width = input_width(&netlist);
+    let vectors = test_vectors(width);
+
+    let suspects = if spec == Spec::Adder { structural_suspects(expressions, width) } else { BTreeMap::new() };
+    for (wire, reason) in suspects.iter() {
+        println!("suspect {wire}: {reason}");
     }
 
-    println!("z = {z}");
+    if let Some(bit) = lowest_failing_bit(&netlist, spec, &vectors) {
+        println!("lowest failing bit: z{bit:02}");
+    }
 
-    fn print_recurse(search: &str, expressions: &ExpressionMap, rename_map: &HashMap<String, Operand>) {
-        if let Some(ren) = rename_map.get(search) {
-            print!("{ren}({search})");
-            return;
-        }
-        
-        let expr = expressions.get(search);
-        let Some(expr) = expr else {
-            print!("{search}");
-            return;
+    let suspects = suspects.into_keys().collect_vec();
+    let mut swaps = Vec::new();
+    let found = (spec == Spec::Adder
+                    && suspects.len() <= 2 * max_pairs + 2
+                    && match_suspects(&mut netlist, &vectors, &suspects, max_pairs, &mut swaps))
+        || {
+            if debug && spec == Spec::Adder {
+                println!("suspects do not pair up, searching bit by bit");
+            }
+            swaps.clear();
+            search_by_bit(&mut netlist, spec, &vectors, max_pairs, &mut swaps)
         };
 
-        let (op, a, b) = expr;
+    Ok(found.then(|| swaps.into_iter().flat_map(|(a, b)| [a, b]).sorted().collect_vec()))
+}
 
-        print!("({search}: {:?} ", op);
-        print_recurse(&a, expressions, rename_map);
-        print!(" ");
-        print_recurse(&b, expressions, rename_map);
-        print!(")");
-    }
+type NormalizedExpr = (Operand, Operand, OPERATOR, Operand);
 
+/// The adder roles inferred for the circuit's wires, and the gates that didn't fit.
+struct Renaming {
+    exprs: Vec<NormalizedExpr>,
+    names: HashMap<String, Operand>,
+    issues: Vec<String>,
+}
+
+/// Renames wires after the adder signal they carry, working outwards from the inputs:
+/// Generate/PSum/Propagate from the x and y bits, then CarryPSum and CarryOut along the
+/// carry chain. Wires that don't fit the pattern keep their `Original` name and are
+/// reported as issues.
+fn rename_wires(expressions: &ExpressionMap) -> Renaming {
     use Operand::*;
 
     let mut normalized_exprs = expressions.iter().map(
@@ -193,34 +333,34 @@ fn main() {
         .collect_vec();
 
     let mut rename_map: HashMap<String, Operand> = HashMap::new();
-    let mut rename_map_reverse: BTreeMap<Operand, String> = BTreeMap::new();
+    let mut issues = Vec::new();
     let mut name_wire = |orig: &mut Operand, name: Operand, rename_map: &mut HashMap<String, Operand>| {
-        let Original(str) = orig else { todo!("renaming {orig} to {name}") };
+        let Original(str) = orig else {
+            issues.push(format!("{orig} would be renamed again to {name}"));
+            return;
+        };
         rename_map.insert(str.to_string(), name.clone());
-        rename_map_reverse.insert(name.clone(), str.to_string());
         *orig = name;
     };
 
     for (res, a, op, b) in normalized_exprs.iter_mut() {
         let update_input = |inp: &mut Operand| {
-            match inp {
-                Original(s) => {
-                    let (s_first, rest) = s.split_at(1);
-                    if s_first == "x" {
-                        *inp = InputX(rest.parse::<u8>().unwrap())
-                    } else if s_first == "y" {
-                        *inp = InputY(rest.parse::<u8>().unwrap())
-                    
-                    };
-                }
-                _ => {}
+            let Original(s) = inp else { return };
+            let bit = |prefix| bus_bit(s, prefix).and_then(|bit| u8::try_from(bit).ok());
+            if let Some(v) = bit('x') {
+                *inp = InputX(v);
+            } else if let Some(v) = bit('y') {
+                *inp = InputY(v);
             }
         };
 
         update_input(a);
         update_input(b);
+        if *a > *b {
+            std::mem::swap(a, b);
+        }
 
-        if let (InputX(v_a), InputY(v_b)) = (a, b) {
+        if let (InputX(v_a), InputY(v_b)) = (&a, &b) {
             if v_a == v_b {
 
                 let new_name = match op {
@@ -234,8 +374,6 @@ fn main() {
         }
     }
 
-
-
     fn remap_operand(op: &mut Operand, rename_map: &HashMap<String, Operand>) {
         if let Original(n) = op {
             if let Some(new_name) = rename_map.get(n) {
@@ -261,25 +399,221 @@ fn main() {
             }
 
             match (a, op, b) {
-                (PSum(v_a), AND, CarryOut(v_b)) => {
-                    if *v_b + 1 == *v_a {
-                        name_wire(res, CarryPSum(*v_a), &mut rename_map);
-                        changed = true;
-                    }
+                (PSum(v_a), AND, CarryOut(v_b)) if *v_b + 1 == *v_a => {
+                    name_wire(res, CarryPSum(*v_a), &mut rename_map);
+                    changed = true;
                 },
-                (Generate(v_a), OR, CarryPSum(v_b)) => {
-                    if *v_a == *v_b {
-                        name_wire(res, CarryOut(*v_a), &mut rename_map);
-                        changed = true;
-                    }
-                }                
+                (Generate(v_a), OR, CarryPSum(v_b)) if *v_a == *v_b => {
+                    name_wire(res, CarryOut(*v_a), &mut rename_map);
+                    changed = true;
+                }
                 _ => {}
             }
         }
 
         if !changed { break }
     }
-    
+
+    // Sum outputs are left alone on purpose, anything else still unnamed is out of place.
+    for (res, a, op, b) in normalized_exprs.iter() {
+        if let Original(wire) = res {
+            if bus_bit(wire, 'z').is_none() {
+                issues.push(format!("{wire} = {a} {op:?} {b} does not fit the adder pattern"));
+            }
+        }
+    }
+    issues.sort();
+
+    Renaming { exprs: normalized_exprs, names: rename_map, issues }
+}
+
+/// The role of a wire for display, if known. Sum outputs aren't renamed since the
+/// S-expression dump expands from them, so those are recognized here as Zn = Sn ^ Cn-1.
+fn wire_role(wire: &str, expressions: &ExpressionMap, rename_map: &HashMap<String, Operand>) -> Option<String> {
+    if let Some(role) = rename_map.get(wire) {
+        return Some(role.to_string());
+    }
+
+    let n = bus_bit(wire, 'z')?;
+    let (OPERATOR::XOR, a, b) = expressions.get(wire)? else { return None };
+    let mut inputs = [rename_map.get(a)?, rename_map.get(b)?];
+    inputs.sort();
+
+    match inputs {
+        [Operand::PSum(s), Operand::CarryOut(c)] if *s as usize == n && *c as usize + 1 == n => Some(format!("Z{n:02}")),
+        _ => None,
+    }
+}
+
+/// Renders the circuit as a Graphviz DOT graph. Each gate is drawn as the wire it drives,
+/// labeled with its name, operator and inferred role. Wires with no inferred role, and
+/// outputs whose role isn't their sum bit, are colored so likely swaps stand out.
+fn circuit_dot(expressions: &ExpressionMap, rename_map: &HashMap<String, Operand>) -> String {
+    let mut dot = String::from("digraph circuit {\n    rankdir=LR;\n    node [shape=box, style=filled, fillcolor=white];\n");
+
+    let inputs = expressions.values()
+        .flat_map(|(_, a, b)| [a, b])
+        .filter(|w| !expressions.contains_key(*w))
+        .collect::<BTreeSet<_>>();
+    for input in inputs {
+        dot += &format!("    \"{input}\" [shape=ellipse, fillcolor=lightblue];\n");
+    }
+
+    // Outputs must carry their sum bit, except the top one which is the final carry. Bit 0
+    // comes straight from the half adder.
+    let top_z = expressions.keys().filter_map(|w| bus_bit(w, 'z')).max().unwrap_or(0);
+    let misplaced = |w: &str, role: &str| match bus_bit(w, 'z') {
+        Some(0) => role != "S00",
+        Some(n) if n == top_z => role != format!("C{:02}", n - 1),
+        Some(n) => role != format!("Z{n:02}"),
+        None => false,
+    };
+    let role_of = |w: &str| wire_role(w, expressions, rename_map).filter(|role| !misplaced(w, role));
+    let unknown = |w: &str| expressions.contains_key(w) && role_of(w).is_none();
+
+    for (out, (op, a, b)) in expressions.iter().sorted() {
+        let role = wire_role(out, expressions, rename_map);
+        let color = match (role_of(out), bus_bit(out, 'z')) {
+            (None, _) => "tomato",
+            (Some(_), Some(_)) => "palegreen",
+            (Some(_), None) => "white",
+        };
+
+        let role = role.unwrap_or_else(|| "?".to_string());
+        dot += &format!("    \"{out}\" [label=\"{out}\\n{op:?} {role}\", fillcolor={color}];\n");
+
+        for input in [a, b] {
+            let style = if unknown(input) { " [color=red]" } else { "" };
+            dot += &format!("    \"{input}\" -> \"{out}\"{style};\n");
+        }
+    }
+
+    dot += "}\n";
+    dot
+}
+
+fn main() {
+    let args = Args::parse();
+
+    let mut swaps: HashMap<&str, &str> = HashMap::new();
+
+    if !args.swaps.is_empty() {
+        for (a, b) in args.swaps.split(',').tuples() {
+            swaps.insert(a, b);
+            swaps.insert(b, a);
+        }
+    }
+
+    let str = std::fs::read_to_string(&args.input_file).expect("Could not read input");
+
+    let mut lines = str.lines();
+
+    let values: ValueMap = lines.by_ref()
+        .take_while(|l| !l.is_empty())
+        .map(|l| scan_fmt!(l, "{}: {d}", String, u8).expect("Parse error"))
+        .map(|(s, v)| (s, v != 0))
+        .collect();
+
+    let expressions_original = lines
+        .map(|l|  {
+            let res = scan_fmt!(l, "{} {} {} -> {}", String, String, String, String);
+            if res.is_err() {
+                panic!("Could not parse {l}: {res:?}");
+            }
+
+            let (a, op, b, out) = res.unwrap();
+            
+            let result = if let Some(s) = swaps.get(out.as_str()) {
+                s.to_string()
+            } else {
+                out
+            };
+
+            let (a, b) = if a < b { (a, b) } else { (b, a) };
+            
+            (result, (OPERATOR::from_str(&op).unwrap(), a, b))
+        }).collect_vec();
+
+    let expressions: ExpressionMap = expressions_original.into_iter().collect();
+
+    if args.dot {
+        let renaming = rename_wires(&expressions);
+        print!("{}", circuit_dot(&expressions, &renaming.names));
+        return;
+    }
+
+    let netlist = match build_netlist(&expressions) {
+        Ok(netlist) => netlist,
+        Err(e) => {
+            println!("{e}");
+            return;
+        }
+    };
+
+    let width = input_width(&netlist);
+    if width > 64 || netlist.bus('z').len() > 64 {
+        println!("buses wider than 64 bits are not supported ({width} input bits, {} output bits)", netlist.bus('z').len());
+        return;
+    }
+
+    if args.find_swaps {
+        match find_swaps(&expressions, args.spec, args.max_swaps, args.debug) {
+            Ok(Some(wires)) => println!("swaps: {}", wires.join(",")),
+            Ok(None) => println!("no set of at most {} swaps makes the circuit match the {} spec", args.max_swaps, args.spec),
+            Err(e) => println!("{e}"),
+        }
+        return;
+    }
+
+    let bus_value = |prefix| netlist.bus(prefix).iter()
+        .enumerate()
+        .fold(0u64, |acc, (i, &w)| acc | (values.get(netlist.name(w)).copied().unwrap_or(false) as u64) << i);
+
+    let z = netlist.evaluate(bus_value('x'), bus_value('y'));
+
+    if args.debug {
+        for (i, &w) in netlist.bus('z').iter().enumerate() {
+            println!("{} = {}", netlist.name(w), (z >> i) & 1);
+        }
+    }
+
+    println!("z = {z}");
+
+    match netlist.find_counterexample(&test_vectors(width), |x, y| args.spec.expected(x, y)) {
+        None => println!("{width}-bit circuit matches the {} spec", args.spec),
+        Some((x, y, z)) => println!("{width}-bit circuit does not match the {} spec: {x} {} {y} gave {z}", args.spec, args.spec.symbol()),
+    }
+
+    if args.spec != Spec::Adder {
+        return;
+    }
+
+    fn print_recurse(search: &str, expressions: &ExpressionMap, rename_map: &HashMap<String, Operand>) {
+        if let Some(ren) = rename_map.get(search) {
+            print!("{ren}({search})");
+            return;
+        }
+        
+        let expr = expressions.get(search);
+        let Some(expr) = expr else {
+            print!("{search}");
+            return;
+        };
+
+        let (op, a, b) = expr;
+
+        print!("({search}: {:?} ", op);
+        print_recurse(a, expressions, rename_map);
+        print!(" ");
+        print_recurse(b, expressions, rename_map);
+        print!(")");
+    }
+
+    let Renaming { exprs: mut normalized_exprs, names: rename_map, issues } = rename_wires(&expressions);
+    let rename_map_reverse: BTreeMap<Operand, String> = rename_map.iter()
+        .map(|(wire, name)| (name.clone(), wire.clone()))
+        .collect();
+
     // Half-adder:
     //      z00 = x00 ^ y00
     //      c00 = x00 & y00
@@ -299,37 +633,22 @@ fn main() {
         
     normalized_exprs.sort_by(|a, b| (&a.1, a.2, &a.3).cmp(&(&b.1, b.2, &b.3)));
 
-    for (res, a, op, b) in normalized_exprs.iter_mut() {
-        
-        
-        if let Generate(v) = a {
-            if *v == 1 {
-                
-            }
-        }
-    }
-
     normalized_exprs.iter().for_each(|(res, a, op, b)| {
         let a_str = a.to_string();
         let b_str = b.to_string();
         let r_str = res.to_string();
-        let a_ren = rename_map_reverse.get(&a).unwrap_or(&a_str);
-        let b_ren = rename_map_reverse.get(&b).unwrap_or(&b_str);
-        let res_ren = rename_map_reverse.get(&res).unwrap_or(&r_str);
+        let a_ren = rename_map_reverse.get(a).unwrap_or(&a_str);
+        let b_ren = rename_map_reverse.get(b).unwrap_or(&b_str);
+        let res_ren = rename_map_reverse.get(res).unwrap_or(&r_str);
         println!("{a}({a_ren}) {op:?} {b}({b_ren}) -> {res}({res_ren})");
     });
 
+    for issue in issues.iter() {
+        println!("unrecognized: {issue}");
+    }
 
-
-    let mut values = ValueMap::new(); 
-
-
-    for col in 0..44 {
-        let x_wire: String = format!("x{col:02}");
-        let y_wire: String = format!("y{col:02}");
-        let z_wire: String = format!("z{col:02}");
-
-        values.clear();
+    for &z in netlist.bus('z') {
+        let z_wire = netlist.name(z);
 
         //
         //  Xn   |   Yn   |  Cn-1  | Cn
@@ -344,9 +663,153 @@ fn main() {
         //
 
         print!("{z_wire}:");
-        print_recurse(&z_wire, &expressions, &rename_map);
+        print_recurse(z_wire, &expressions, &rename_map);
         println!();
     }
 
     println!("swaps: {}", swaps.keys().sorted().join(","));
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Scrambled but predictable names for the internal wires of bit `i`.
+    fn adder_wire(kind: &str, i: usize) -> String {
+        format!("{kind}{}{i}", (b'a' + (i * 7 % 26) as u8) as char)
+    }
+
+    /// A ripple-carry adder built from the usual five gates per bit.
+    fn build_adder(width: usize) -> ExpressionMap {
+        let mut expressions = ExpressionMap::new();
+        let mut add_gate = |out: String, op, a: &str, b: &str| {
+            let (a, b) = if a < b { (a, b) } else { (b, a) };
+            expressions.insert(out, (op, a.to_string(), b.to_string()));
+        };
+
+        let mut carry = adder_wire("c", 0);
+        add_gate(wire_name('z', 0), OPERATOR::XOR, "x00", "y00");
+        add_gate(carry.clone(), OPERATOR::AND, "x00", "y00");
+        for i in 1..width {
+            let (x, y) = (wire_name('x', i), wire_name('y', i));
+            let c = if i + 1 == width { wire_name('z', width) } else { adder_wire("c", i) };
+            add_gate(adder_wire("s", i), OPERATOR::XOR, &x, &y);
+            add_gate(adder_wire("g", i), OPERATOR::AND, &x, &y);
+            add_gate(wire_name('z', i), OPERATOR::XOR, &adder_wire("s", i), &carry);
+            add_gate(adder_wire("t", i), OPERATOR::AND, &adder_wire("s", i), &carry);
+            add_gate(c.clone(), OPERATOR::OR, &adder_wire("g", i), &adder_wire("t", i));
+            carry = c;
+        }
+
+        expressions
+    }
+
+    fn swap_outputs(expressions: &mut ExpressionMap, a: &str, b: &str) {
+        let expr_a = expressions.remove(a).unwrap();
+        let expr_b = expressions.insert(b.to_string(), expr_a).unwrap();
+        expressions.insert(a.to_string(), expr_b);
+    }
+
+    #[test]
+    fn correct_adder_passes() {
+        let width = 12;
+        let expressions = build_adder(width);
+        let netlist = build_netlist(&expressions).unwrap();
+        assert!(structural_suspects(&expressions, width).is_empty());
+        assert_eq!(lowest_failing_bit(&netlist, Spec::Adder, &test_vectors(width)), None);
+        assert_eq!(find_swaps(&expressions, Spec::Adder, 4, false), Ok(Some(vec![])));
+    }
+
+    #[test]
+    fn finds_swaps() {
+        let width = 16;
+        let mut expressions = build_adder(width);
+        let swapped = [
+            ("z05".to_string(), adder_wire("c", 5)),
+            (adder_wire("s", 8), adder_wire("g", 8)),
+            ("z11".to_string(), adder_wire("t", 11)),
+            (adder_wire("g", 14), "z14".to_string()),
+        ];
+
+        for (a, b) in swapped.iter() {
+            swap_outputs(&mut expressions, a, b);
+        }
+
+        let netlist = build_netlist(&expressions).unwrap();
+        assert!(lowest_failing_bit(&netlist, Spec::Adder, &test_vectors(width)).is_some());
+
+        let expected = swapped.iter().flat_map(|(a, b)| [a.clone(), b.clone()]).sorted().collect_vec();
+        assert_eq!(find_swaps(&expressions, Spec::Adder, 4, false), Ok(Some(expected)));
+    }
+
+    #[test]
+    fn finds_swaps_without_suspects() {
+        // Exchanging two half sums keeps every gate in a plausible position.
+        let width = 10;
+        let mut expressions = build_adder(width);
+        swap_outputs(&mut expressions, &adder_wire("s", 3), &adder_wire("s", 6));
+        assert!(structural_suspects(&expressions, width).is_empty());
+
+        let expected = [adder_wire("s", 3), adder_wire("s", 6)].into_iter().sorted().collect_vec();
+        assert_eq!(find_swaps(&expressions, Spec::Adder, 2, false), Ok(Some(expected)));
+
+        // Exchanging two carries loops the carry chain back on itself.
+        let mut expressions = build_adder(width);
+        swap_outputs(&mut expressions, &adder_wire("c", 3), &adder_wire("c", 6));
+        assert!(matches!(find_swaps(&expressions, Spec::Adder, 2, false), Err(NetlistError::Cycle(_))));
+    }
+
+    #[test]
+    fn dot_marks_unknown_roles() {
+        let width = 8;
+        let mut expressions = build_adder(width);
+        let dot = circuit_dot(&expressions, &rename_wires(&expressions).names);
+        assert!(!dot.contains("tomato"), "{dot}");
+        assert!(dot.contains("\"z03\" [label=\"z03\\nXOR Z03\", fillcolor=palegreen];"), "{dot}");
+
+        swap_outputs(&mut expressions, "z05", &adder_wire("t", 5));
+        let dot = circuit_dot(&expressions, &rename_wires(&expressions).names);
+        assert!(dot.contains("\"z05\" [label=\"z05\\nAND PS05\", fillcolor=tomato];"), "{dot}");
+        assert!(dot.contains(&format!("\"{}\" [label=\"{0}\\nXOR ?\", fillcolor=tomato];", adder_wire("t", 5))), "{dot}");
+    }
+
+    /// z = x & y, one gate per bit.
+    fn build_and(width: usize) -> ExpressionMap {
+        (0..width)
+            .map(|i| (wire_name('z', i), (OPERATOR::AND, wire_name('x', i), wire_name('y', i))))
+            .collect()
+    }
+
+    #[test]
+    fn checks_against_spec() {
+        let width = 20;
+        let vectors = test_vectors(width);
+
+        let adder = build_netlist(&build_adder(width)).unwrap();
+        assert_eq!(lowest_failing_bit(&adder, Spec::Adder, &vectors), None);
+        assert!(lowest_failing_bit(&adder, Spec::Subtractor, &vectors).is_some());
+
+        let and = build_netlist(&build_and(width)).unwrap();
+        assert_eq!(lowest_failing_bit(&and, Spec::And, &vectors), None);
+        assert_eq!(lowest_failing_bit(&and, Spec::Adder, &vectors), Some(0));
+
+        let mut expressions = build_and(width);
+        swap_outputs(&mut expressions, "z04", "z09");
+        assert_eq!(find_swaps(&expressions, Spec::And, 1, false), Ok(Some(vec!["z04".to_string(), "z09".to_string()])));
+    }
+
+    #[test]
+    fn renaming_reports_unexpected_shapes() {
+        assert!(rename_wires(&build_adder(6)).issues.is_empty());
+
+        // Internal wires may look like inputs, and gates may mix bits.
+        let mut expressions = build_adder(6);
+        expressions.insert("xab".to_string(), (OPERATOR::AND, "x01".to_string(), "y02".to_string()));
+        expressions.insert("yab".to_string(), (OPERATOR::OR, "x03".to_string(), "xab".to_string()));
+        let issues = rename_wires(&expressions).issues;
+        assert_eq!(issues, [
+            "xab = x01 AND y02 does not fit the adder pattern",
+            "yab = x03 OR xab does not fit the adder pattern",
+        ]);
+    }
+}
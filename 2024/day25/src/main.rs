@@ -6,41 +6,94 @@ struct Args {
     #[arg(default_value = "input_sample.txt")]
     input_file: String,
 
+    /// List the lock/key pairs that fit.
     #[arg(short, long, default_value_t = false)]
     debug: bool,
 }
 
-fn check_no_overlap(lock: &Vec<&[u8]>, key: &Vec<&[u8]>, args: &Args) -> bool
-{
-    let res = lock.iter().zip(key).all(
-        |(&l, &k)| 
-            l.iter().zip(k).all(|(&l, &k)| l != b'#' || k != b'#'));
+/// A lock or key as the height of each column past its base row, and as a bitmask of its
+/// filled cells in row-major order.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Schematic {
+    is_lock: bool,
+    heights: Vec<usize>,
+    mask: u64,
+}
 
-    if args.debug {
-        lock.iter().zip(key).for_each(|(l, k)| {
-            println!("{} {}", std::str::from_utf8(l).unwrap(), std::str::from_utf8(k).unwrap());
-        });
+/// Locks have their top row filled and keys their bottom row. Every column has to be solid
+/// from the base for the heights to describe it.
+fn parse_schematic(rows: &[&[u8]]) -> Schematic {
+    let cols = rows[0].len();
+    assert!(rows.len() * cols <= 64, "{}x{cols} schematics don't fit in a u64 mask", rows.len());
 
-        println!("RESULT: {res}");
-        println!();
-    }
+    let is_lock = rows[0].iter().all(|&c| c == b'#');
+    let mask = rows.iter()
+        .flat_map(|r| r.iter())
+        .enumerate()
+        .filter(|(_, &c)| c == b'#')
+        .fold(0u64, |m, (i, _)| m | 1 << i);
+
+    let heights = (0..cols).map(|c| {
+        let mut from_base = rows.iter().map(|r| r[c] == b'#').collect_vec();
+        if !is_lock {
+            from_base.reverse();
+        }
+
+        let filled = from_base.iter().take_while(|&&f| f).count();
+        assert!(filled > 0 && from_base[filled..].iter().all(|&f| !f), "column {c} is not solid from the base");
+        filled - 1
+    }).collect();
 
-    res
+    Schematic { is_lock, heights, mask }
 }
 
-fn main() {
+fn fits(lock: &Schematic, key: &Schematic) -> bool {
+    lock.mask & key.mask == 0
+}
 
-    let args = Args::parse();
+/// Counts the pairs where every column fits, lock + key height <= `space`, without comparing
+/// every pair. Keys are tallied by height vector and the tally is prefix-summed along each
+/// column, so each entry holds the number of keys no taller in any column. Each lock then
+/// looks up the entry for the room it leaves. Falls back to comparing masks when the table
+/// would be too big.
+fn count_fitting_pairs(locks: &[Schematic], keys: &[Schematic], space: usize) -> usize {
+    let Some(cols) = locks.iter().chain(keys).map(|s| s.heights.len()).next() else {
+        return 0;
+    };
 
-    let input = std::fs::read_to_string(&args.input_file).expect("opened input file");
+    let side = space + 1;
+    let Some(cells) = side.checked_pow(cols as u32).filter(|&c| c <= 1 << 24) else {
+        return locks.iter().cartesian_product(keys).filter(|(l, k)| fits(l, k)).count();
+    };
 
-    let mut keys = Vec::new();
-    let mut locks = Vec::new();
+    let index = |heights: &mut dyn Iterator<Item = usize>| heights.fold(0, |i, h| i * side + h);
 
+    let mut counts = vec![0usize; cells];
+    for key in keys.iter().filter(|k| k.heights.iter().all(|&h| h <= space)) {
+        counts[index(&mut key.heights.iter().copied())] += 1;
+    }
+
+    let mut stride = 1;
+    for _ in 0..cols {
+        for i in 0..cells {
+            if (i / stride) % side != 0 {
+                counts[i] += counts[i - stride];
+            }
+        }
+        stride *= side;
+    }
+
+    locks.iter()
+        .filter_map(|l| l.heights.iter().map(|&h| space.checked_sub(h)).collect::<Option<Vec<_>>>())
+        .map(|room| counts[index(&mut room.into_iter())])
+        .sum()
+}
+
+fn parse_schematics(input: &str) -> (Vec<Schematic>, usize) {
     let mut lines = input.lines();
     let mut rows = None;
     let mut cols = None;
-    let mut fitting_pairs = 0usize;
+    let mut schematics = Vec::new();
     loop {
         let new_schematic = lines.by_ref()
             .take_while(|l| !l.is_empty())
@@ -58,16 +111,78 @@ fn main() {
         assert_ne!(cc, 0);
         assert!(new_schematic.iter().all(|r| r.len() == cc));
 
-        let (to_check, to_push) =
-            if new_schematic[0][0] == b'#' {
-                (&keys, &mut locks)
-            } else {
-                (&locks, &mut keys)
-            };
-        
-        fitting_pairs += to_check.iter().filter(|&c| check_no_overlap(c, &new_schematic, &args)).count();
-        to_push.push(new_schematic);
+        schematics.push(parse_schematic(&new_schematic));
+    }
+
+    // The base rows of a lock and key can't overlap, which leaves this much room between them.
+    (schematics, rows.unwrap_or(2).saturating_sub(2))
+}
+
+fn main() {
+
+    let args = Args::parse();
+
+    let input = std::fs::read_to_string(&args.input_file).expect("opened input file");
+
+    let (schematics, space) = parse_schematics(&input);
+    let (locks, keys): (Vec<_>, Vec<_>) = schematics.into_iter().partition(|s| s.is_lock);
+
+    let fitting_pairs = count_fitting_pairs(&locks, &keys, space);
+
+    if args.debug {
+        let mut listed = 0usize;
+        for ((i, lock), (j, key)) in locks.iter().enumerate().cartesian_product(keys.iter().enumerate()) {
+            if fits(lock, key) {
+                println!("lock {i} {:?} fits key {j} {:?}", lock.heights, key.heights);
+                listed += 1;
+            }
+        }
+
+        assert_eq!(listed, fitting_pairs);
     }
 
     dbg!(fitting_pairs);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sample() {
+        let (schematics, space) = parse_schematics(include_str!("../input_sample.txt"));
+        assert_eq!(space, 5);
+        assert_eq!(schematics[0], Schematic { is_lock: true, heights: vec![0, 5, 3, 4, 3], mask: schematics[0].mask });
+        assert_eq!(schematics[2].heights, [5, 0, 2, 1, 3]);
+
+        let (locks, keys): (Vec<_>, Vec<_>) = schematics.into_iter().partition(|s| s.is_lock);
+        assert_eq!(count_fitting_pairs(&locks, &keys, space), 3);
+    }
+
+    #[test]
+    fn counting_matches_masks() {
+        let mut state = 0x9e37_79b9_7f4a_7c15u64;
+        let mut next = |n: usize| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            (state % n as u64) as usize
+        };
+
+        let (rows, cols) = (7, 5);
+        let mut schematic = |is_lock: bool| {
+            let heights = (0..cols).map(|_| next(rows)).collect_vec();
+            let text = (0..rows).map(|r| {
+                let from_base = if is_lock { r } else { rows - 1 - r };
+                heights.iter().map(|&h| if from_base <= h { b'#' } else { b'.' }).collect_vec()
+            }).collect_vec();
+            parse_schematic(&text.iter().map(|r| r.as_slice()).collect_vec())
+        };
+
+        let locks = (0..150).map(|_| schematic(true)).collect_vec();
+        let keys = (0..170).map(|_| schematic(false)).collect_vec();
+        let pairwise = locks.iter().cartesian_product(keys.iter()).filter(|(l, k)| fits(l, k)).count();
+        assert!(pairwise > 0);
+        assert_eq!(count_fitting_pairs(&locks, &keys, rows - 2), pairwise);
+    }
+}
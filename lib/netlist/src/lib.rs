@@ -0,0 +1,394 @@
+use std::{collections::HashMap, fmt, str::FromStr};
+use thiserror::Error;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum GateOp {
+    And,
+    Or,
+    Xor,
+}
+
+impl GateOp {
+    /// Applies the gate to 64 independent bits at once.
+    pub fn apply(self, a: u64, b: u64) -> u64 {
+        match self {
+            GateOp::And => a & b,
+            GateOp::Or => a | b,
+            GateOp::Xor => a ^ b,
+        }
+    }
+}
+
+impl FromStr for GateOp {
+    type Err = NetlistError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "AND" => Ok(GateOp::And),
+            "OR" => Ok(GateOp::Or),
+            "XOR" => Ok(GateOp::Xor),
+            _ => Err(NetlistError::ParseError(format!("unknown gate {s}"))),
+        }
+    }
+}
+
+impl fmt::Display for GateOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", match self { GateOp::And => "AND", GateOp::Or => "OR", GateOp::Xor => "XOR" })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Gate {
+    pub op: GateOp,
+    pub a: usize,
+    pub b: usize,
+    pub out: usize,
+}
+
+#[derive(Debug, Clone, Error, PartialEq, Eq)]
+pub enum NetlistError {
+    #[error("Parsing error: {0}")]
+    ParseError(String),
+    #[error("Wire {0} is driven by more than one gate")]
+    MultipleDrivers(String),
+    #[error("Wire {0} is not driven by any gate and is not an x or y input")]
+    Undriven(String),
+    #[error("Bus {0} is missing bit {1}")]
+    BusGap(char, usize),
+    #[error("Unknown wire {0}")]
+    UnknownWire(String),
+    #[error("Cycle through wires {}", .0.join(" -> "))]
+    Cycle(Vec<String>),
+}
+
+/// The bit number of wires named like `x07`.
+pub fn bus_bit(name: &str, prefix: char) -> Option<usize> {
+    let digits = name.strip_prefix(prefix)?;
+    if digits.is_empty() || !digits.bytes().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    digits.parse().ok()
+}
+
+/// A gate-level circuit with wires numbered densely and gates kept in topological order.
+///
+/// Wires named `xNN`, `yNN` and `zNN` form the input and output buses. Evaluation runs 64
+/// input vectors at once, one per bit of a `u64`.
+#[derive(Debug, Clone)]
+pub struct Netlist {
+    names: Vec<String>,
+    index: HashMap<String, usize>,
+    gates: Vec<Gate>,
+    driver: Vec<Option<usize>>,
+    order: Vec<usize>,
+    x: Vec<usize>,
+    y: Vec<usize>,
+    z: Vec<usize>,
+}
+
+impl Netlist {
+    /// Builds a netlist from `(out, op, a, b)` tuples.
+    pub fn new<S: AsRef<str>>(gates: impl IntoIterator<Item = (S, GateOp, S, S)>) -> Result<Self, NetlistError> {
+        let mut netlist = Netlist {
+            names: Vec::new(),
+            index: HashMap::new(),
+            gates: Vec::new(),
+            driver: Vec::new(),
+            order: Vec::new(),
+            x: Vec::new(),
+            y: Vec::new(),
+            z: Vec::new(),
+        };
+
+        for (out, op, a, b) in gates {
+            let a = netlist.intern(a.as_ref());
+            let b = netlist.intern(b.as_ref());
+            let out = netlist.intern(out.as_ref());
+            if netlist.driver[out].is_some() {
+                return Err(NetlistError::MultipleDrivers(netlist.names[out].clone()));
+            }
+
+            netlist.driver[out] = Some(netlist.gates.len());
+            netlist.gates.push(Gate{op, a, b, out});
+        }
+
+        for prefix in ['x', 'y', 'z'] {
+            let mut bits = netlist.names.iter()
+                .enumerate()
+                .filter_map(|(w, name)| Some((bus_bit(name, prefix)?, w)))
+                .collect::<Vec<_>>();
+            bits.sort();
+
+            if let Some(bit) = bits.iter().enumerate().position(|(i, (bit, _))| i != *bit) {
+                return Err(NetlistError::BusGap(prefix, bit));
+            }
+
+            let bus = bits.into_iter().map(|(_, w)| w).collect();
+            match prefix {
+                'x' => netlist.x = bus,
+                'y' => netlist.y = bus,
+                _ => netlist.z = bus,
+            }
+        }
+
+        if let Some(w) = (0..netlist.names.len()).find(|&w| netlist.driver[w].is_none()
+            && bus_bit(&netlist.names[w], 'x').is_none()
+            && bus_bit(&netlist.names[w], 'y').is_none()) {
+            return Err(NetlistError::Undriven(netlist.names[w].clone()));
+        }
+
+        netlist.order = netlist.topological_order()?;
+        Ok(netlist)
+    }
+
+    /// Parses `a OP b -> out` lines. Blank lines and `wire: value` lines are skipped.
+    pub fn parse(text: &str) -> Result<Self, NetlistError> {
+        let gates = text.lines()
+            .map(str::trim)
+            .filter(|l| !l.is_empty() && !l.contains(':'))
+            .map(|l| {
+                let parts = l.split_whitespace().collect::<Vec<_>>();
+                let &[a, op, b, "->", out] = parts.as_slice() else {
+                    return Err(NetlistError::ParseError(format!("expected 'a OP b -> out', got '{l}'")));
+                };
+                Ok((out, op.parse::<GateOp>()?, a, b))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Self::new(gates)
+    }
+
+    fn intern(&mut self, name: &str) -> usize {
+        if let Some(&w) = self.index.get(name) {
+            return w;
+        }
+
+        self.names.push(name.to_string());
+        self.driver.push(None);
+        self.index.insert(name.to_string(), self.names.len() - 1);
+        self.names.len() - 1
+    }
+
+    fn topological_order(&self) -> Result<Vec<usize>, NetlistError> {
+        let mut consumers = vec![Vec::new(); self.names.len()];
+        let mut pending = vec![0u8; self.gates.len()];
+        for (g, gate) in self.gates.iter().enumerate() {
+            for input in [gate.a, gate.b] {
+                if self.driver[input].is_some() {
+                    consumers[input].push(g);
+                    pending[g] += 1;
+                }
+            }
+        }
+
+        let mut order = (0..self.gates.len()).filter(|&g| pending[g] == 0).collect::<Vec<_>>();
+        let mut next = 0;
+        while next < order.len() {
+            let out = self.gates[order[next]].out;
+            for &g in consumers[out].iter() {
+                pending[g] -= 1;
+                if pending[g] == 0 {
+                    order.push(g);
+                }
+            }
+            next += 1;
+        }
+
+        if order.len() == self.gates.len() {
+            return Ok(order);
+        }
+
+        // Every gate left over has an input driven by another left over gate, so walking
+        // backwards from any of them must eventually repeat.
+        let mut path: Vec<usize> = Vec::new();
+        let mut g = (0..self.gates.len()).find(|&g| pending[g] != 0).unwrap();
+        loop {
+            if let Some(start) = path.iter().position(|&p| p == g) {
+                let mut cycle = path[start..].iter().rev().map(|&p| self.names[self.gates[p].out].clone()).collect::<Vec<_>>();
+                cycle.push(cycle[0].clone());
+                return Err(NetlistError::Cycle(cycle));
+            }
+
+            path.push(g);
+            let gate = self.gates[g];
+            g = [gate.a, gate.b].into_iter()
+                .filter_map(|w| self.driver[w])
+                .find(|&d| pending[d] != 0)
+                .unwrap();
+        }
+    }
+
+    pub fn wire(&self, name: &str) -> Option<usize> {
+        self.index.get(name).copied()
+    }
+
+    pub fn name(&self, wire: usize) -> &str {
+        &self.names[wire]
+    }
+
+    pub fn wire_count(&self) -> usize {
+        self.names.len()
+    }
+
+    pub fn gates(&self) -> &[Gate] {
+        &self.gates
+    }
+
+    pub fn driver(&self, wire: usize) -> Option<&Gate> {
+        self.driver[wire].map(|g| &self.gates[g])
+    }
+
+    /// Wire indices of `x00..`, `y00..` or `z00..`, in bit order.
+    pub fn bus(&self, prefix: char) -> &[usize] {
+        match prefix {
+            'x' => &self.x,
+            'y' => &self.y,
+            'z' => &self.z,
+            _ => &[],
+        }
+    }
+
+    /// Gate driven wires that `wire` depends on, including itself.
+    pub fn fan_in(&self, wire: usize) -> Vec<usize> {
+        let mut seen = vec![false; self.names.len()];
+        let mut stack = vec![wire];
+        let mut cone = Vec::new();
+        while let Some(w) = stack.pop() {
+            if seen[w] { continue; }
+            seen[w] = true;
+            if let Some(gate) = self.driver(w) {
+                cone.push(w);
+                stack.extend([gate.a, gate.b]);
+            }
+        }
+        cone
+    }
+
+    /// Exchanges the gates driving two wires. The netlist is left unchanged if that would
+    /// create a cycle.
+    pub fn swap_outputs(&mut self, a: &str, b: &str) -> Result<(), NetlistError> {
+        let wa = self.wire(a).ok_or_else(|| NetlistError::UnknownWire(a.to_string()))?;
+        let wb = self.wire(b).ok_or_else(|| NetlistError::UnknownWire(b.to_string()))?;
+
+        let exchange = |netlist: &mut Netlist| {
+            netlist.driver.swap(wa, wb);
+            for w in [wa, wb] {
+                if let Some(g) = netlist.driver[w] {
+                    netlist.gates[g].out = w;
+                }
+            }
+        };
+
+        exchange(self);
+        match self.topological_order() {
+            Ok(order) => {
+                self.order = order;
+                Ok(())
+            },
+            Err(e) => {
+                exchange(self);
+                Err(e)
+            },
+        }
+    }
+
+    /// Evaluates one bit plane per wire: bit `v` of `x_planes[i]` is bit `i` of the x input for
+    /// vector `v`. Returns the z planes in the same layout.
+    pub fn evaluate_planes(&self, x_planes: &[u64], y_planes: &[u64]) -> Vec<u64> {
+        let mut values = vec![0u64; self.names.len()];
+        for (w, plane) in self.x.iter().zip(x_planes).chain(self.y.iter().zip(y_planes)) {
+            values[*w] = *plane;
+        }
+
+        for &g in self.order.iter() {
+            let gate = self.gates[g];
+            values[gate.out] = gate.op.apply(values[gate.a], values[gate.b]);
+        }
+
+        self.z.iter().map(|&w| values[w]).collect()
+    }
+
+    /// Evaluates any number of `(x, y)` pairs, 64 at a time. Only the low 64 bits of z are kept.
+    pub fn evaluate_many(&self, vectors: &[(u64, u64)]) -> Vec<u64> {
+        let mut results = Vec::with_capacity(vectors.len());
+        for chunk in vectors.chunks(64) {
+            let planes = |bus: &[usize], pick: fn(&(u64, u64)) -> u64| -> Vec<u64> {
+                (0..bus.len().min(64))
+                    .map(|i| chunk.iter().enumerate().fold(0, |acc, (v, pair)| acc | ((pick(pair) >> i) & 1) << v))
+                    .collect()
+            };
+
+            let z_planes = self.evaluate_planes(&planes(&self.x, |p| p.0), &planes(&self.y, |p| p.1));
+            results.extend((0..chunk.len()).map(|v| {
+                z_planes.iter().take(64).enumerate().fold(0, |acc, (i, plane)| acc | ((plane >> v) & 1) << i)
+            }));
+        }
+        results
+    }
+
+    pub fn evaluate(&self, x: u64, y: u64) -> u64 {
+        self.evaluate_many(&[(x, y)])[0]
+    }
+
+    /// The first vector where the circuit disagrees with `expected`, as `(x, y, z)`.
+    pub fn find_counterexample(&self, vectors: &[(u64, u64)], expected: impl Fn(u64, u64) -> u64) -> Option<(u64, u64, u64)> {
+        let mask = if self.z.len() >= 64 { u64::MAX } else { (1 << self.z.len()) - 1 };
+        vectors.chunks(64)
+            .flat_map(|chunk| chunk.iter().copied().zip(self.evaluate_many(chunk)).collect::<Vec<_>>())
+            .find(|&((x, y), z)| z != expected(x, y) & mask)
+            .map(|((x, y), z)| (x, y, z))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ADDER: &str = "\
+x00 XOR y00 -> z00
+x00 AND y00 -> c00
+x01 XOR y01 -> s01
+x01 AND y01 -> g01
+s01 XOR c00 -> z01
+s01 AND c00 -> t01
+g01 OR t01 -> z02
+";
+
+    #[test]
+    fn two_bit_adder() {
+        let netlist = Netlist::parse(ADDER).unwrap();
+        assert_eq!(netlist.bus('x').len(), 2);
+        assert_eq!(netlist.bus('z').len(), 3);
+
+        let vectors = (0..4).flat_map(|x| (0..4).map(move |y| (x, y))).collect::<Vec<_>>();
+        assert_eq!(netlist.evaluate_many(&vectors), vectors.iter().map(|(x, y)| x + y).collect::<Vec<_>>());
+        assert_eq!(netlist.evaluate(3, 3), 6);
+        assert_eq!(netlist.find_counterexample(&vectors, |x, y| x + y), None);
+        assert_eq!(netlist.find_counterexample(&vectors, |x, y| x | y), Some((1, 1, 2)));
+    }
+
+    #[test]
+    fn swaps_and_cycles() {
+        let mut netlist = Netlist::parse(ADDER).unwrap();
+        netlist.swap_outputs("z01", "z02").unwrap();
+        assert_eq!(netlist.evaluate(2, 0), 0b100);
+
+        // t01 depends on c00, so driving c00 from t01's gate loops.
+        let err = netlist.swap_outputs("c00", "t01").unwrap_err();
+        assert_eq!(err, NetlistError::Cycle(vec!["c00".to_string(), "c00".to_string()]));
+        assert_eq!(netlist.evaluate(2, 0), 0b100);
+
+        let err = Netlist::parse("a AND x00 -> b\nb OR y00 -> a\nb XOR a -> z00").unwrap_err();
+        assert!(matches!(err, NetlistError::Cycle(_)), "{err}");
+        assert_eq!(err.to_string(), "Cycle through wires a -> b -> a");
+    }
+
+    #[test]
+    fn bad_netlists() {
+        assert_eq!(Netlist::parse("x00 AND q -> z00").unwrap_err(), NetlistError::Undriven("q".to_string()));
+        assert_eq!(Netlist::parse("x00 AND y00 -> z00\nx00 OR y00 -> z00").unwrap_err(), NetlistError::MultipleDrivers("z00".to_string()));
+        assert_eq!(Netlist::parse("x00 AND y00 -> z01").unwrap_err(), NetlistError::BusGap('z', 0));
+        assert!(matches!(Netlist::parse("x00 NAND y00 -> z00"), Err(NetlistError::ParseError(_))));
+    }
+}
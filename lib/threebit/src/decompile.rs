@@ -0,0 +1,467 @@
+//! Turns machine code back into structured, Rust-like pseudo-code.
+//!
+//! Backward `jnz` instructions become `loop { ... if A == 0 { break; } }` blocks. Within a
+//! straight-line run of instructions register writes are folded into expressions over the
+//! values at the start of the run, and only registers that are read later are written back.
+//! The result can also be compiled into a tree of closures, which runs much faster than
+//! stepping the interpreter.
+
+use std::fmt::{self, Write};
+use num_traits::FromPrimitive;
+use thiserror::Error;
+
+use crate::*;
+
+const REG_NAMES: [char; REG_COUNT] = ['A', 'B', 'C'];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Expr {
+    Reg(usize),
+    Lit(MachineWord),
+    Shr(Box<Expr>, Box<Expr>),
+    Xor(Box<Expr>, Box<Expr>),
+    Mod8(Box<Expr>),
+}
+
+impl Expr {
+    /// Upper bound on the number of significant bits, if known.
+    fn bits(&self) -> Option<u32> {
+        match self {
+            Expr::Reg(_) => None,
+            Expr::Lit(v) => Some(MachineWord::BITS - v.leading_zeros()),
+            Expr::Shr(a, _) => a.bits(),
+            Expr::Xor(a, b) => Some(a.bits()?.max(b.bits()?)),
+            Expr::Mod8(_) => Some(3),
+        }
+    }
+
+    fn shr(a: Expr, b: Expr) -> Expr {
+        match (a, b) {
+            (Expr::Lit(a), Expr::Lit(b)) => Expr::Lit(a.checked_shr(b.try_into().unwrap_or(u32::MAX)).unwrap_or(0)),
+            (a, Expr::Lit(0)) => a,
+            (a, b) => Expr::Shr(Box::new(a), Box::new(b)),
+        }
+    }
+
+    fn xor(a: Expr, b: Expr) -> Expr {
+        match (a, b) {
+            (Expr::Lit(a), Expr::Lit(b)) => Expr::Lit(a ^ b),
+            (a, Expr::Lit(0)) | (Expr::Lit(0), a) => a,
+            (Expr::Xor(x, y), Expr::Lit(b)) if matches!(*y, Expr::Lit(_)) => {
+                let Expr::Lit(a) = *y else { unreachable!() };
+                Expr::xor(*x, Expr::Lit(a ^ b))
+            },
+            (Expr::Lit(a), b) => Expr::xor(b, Expr::Lit(a)),
+            (a, b) => Expr::Xor(Box::new(a), Box::new(b)),
+        }
+    }
+
+    fn mod8(a: Expr) -> Expr {
+        match a {
+            Expr::Lit(v) => Expr::Lit(v % 8),
+            a if a.bits().is_some_and(|b| b <= 3) => a,
+            a => Expr::Mod8(Box::new(a)),
+        }
+    }
+
+    fn uses(&self, reg: usize) -> bool {
+        match self {
+            Expr::Reg(r) => *r == reg,
+            Expr::Lit(_) => false,
+            Expr::Shr(a, b) | Expr::Xor(a, b) => a.uses(reg) || b.uses(reg),
+            Expr::Mod8(a) => a.uses(reg),
+        }
+    }
+
+    /// Rust operator precedence, higher binds tighter.
+    fn precedence(&self) -> u8 {
+        match self {
+            Expr::Reg(_) | Expr::Lit(_) => 4,
+            Expr::Mod8(_) => 3,
+            Expr::Shr(..) => 2,
+            Expr::Xor(..) => 1,
+        }
+    }
+
+    fn fmt_child(&self, f: &mut fmt::Formatter<'_>, parent: u8, right: bool) -> fmt::Result {
+        let p = self.precedence();
+        if p < parent || (right && p == parent) {
+            write!(f, "({self})")
+        } else {
+            write!(f, "{self}")
+        }
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let p = self.precedence();
+        match self {
+            Expr::Reg(r) => write!(f, "{}", REG_NAMES[*r]),
+            Expr::Lit(v) => write!(f, "{v}"),
+            Expr::Shr(a, b) => {a.fmt_child(f, p, false)?; write!(f, " >> ")?; b.fmt_child(f, p, true)},
+            Expr::Xor(a, b) => {a.fmt_child(f, p, false)?; write!(f, " ^ ")?; b.fmt_child(f, p, true)},
+            Expr::Mod8(a) => {a.fmt_child(f, p, false)?; write!(f, " % 8")},
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Stmt {
+    /// Simultaneous assignment, every right hand side sees the old register values.
+    Assign(Vec<(usize, Expr)>),
+    Out(Expr),
+    /// Runs the body, then repeats while A is non-zero.
+    Loop(Vec<Stmt>),
+}
+
+#[derive(Debug, Clone, Error, PartialEq, Eq)]
+pub enum DecompileError {
+    #[error("Jump at pc {pc} to {target} does not form a loop")]
+    Unstructured{ pc: usize, target: usize },
+    #[error(transparent)]
+    Machine(#[from] MachineError),
+}
+
+/// Register bit masks for liveness.
+const fn reg_bit(r: usize) -> u8 { 1 << r }
+
+fn combo_uses(operand: u8) -> u8 {
+    match operand {
+        4..=6 => reg_bit((operand - 4) as usize),
+        _ => 0,
+    }
+}
+
+fn uses_defs(instr: Instr, operand: u8) -> (u8, u8) {
+    match instr {
+        Instr::Adv => (reg_bit(A) | combo_uses(operand), reg_bit(A)),
+        Instr::Bdv => (reg_bit(A) | combo_uses(operand), reg_bit(B)),
+        Instr::Cdv => (reg_bit(A) | combo_uses(operand), reg_bit(C)),
+        Instr::Bxl => (reg_bit(B), reg_bit(B)),
+        Instr::Bst => (combo_uses(operand), reg_bit(B)),
+        Instr::Jnz => (reg_bit(A), 0),
+        Instr::Bxc => (reg_bit(B) | reg_bit(C), reg_bit(B)),
+        Instr::Out => (combo_uses(operand), 0),
+    }
+}
+
+struct Decompiler {
+    instrs: Vec<(Instr, u8)>,
+    /// Registers read before being written on some path starting at each instruction.
+    live_in: Vec<u8>,
+}
+
+impl Decompiler {
+    fn new(machine_code: &[u8]) -> Result<Self, DecompileError> {
+        let instrs = machine_code.chunks_exact(2)
+            .enumerate()
+            .map(|(i, c)| Instr::from_u8(c[0])
+                .map(|instr| (instr, c[1]))
+                .ok_or(MachineError::InvalidOpcode{pc: i * 2, opcode: c[0]}))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        for (i, &(instr, operand)) in instrs.iter().enumerate() {
+            if instr == Instr::Jnz && (operand % 2 != 0 || operand as usize / 2 > i) {
+                return Err(DecompileError::Unstructured{pc: i * 2, target: operand as usize});
+            }
+
+            if operand == 7 && matches!(instr, Instr::Adv | Instr::Bdv | Instr::Cdv | Instr::Bst | Instr::Out) {
+                return Err(MachineError::InvalidOperand{pc: i * 2, operand}.into());
+            }
+        }
+
+        // Loops must nest: a jump may not land strictly inside another loop it doesn't contain.
+        let loops = instrs.iter().enumerate()
+            .filter(|(_, (instr, _))| *instr == Instr::Jnz)
+            .map(|(j, (_, operand))| (*operand as usize / 2, j))
+            .collect::<Vec<_>>();
+        for &(t1, j1) in loops.iter() {
+            for &(t2, j2) in loops.iter() {
+                if t1 < t2 && t2 <= j1 && j1 < j2 {
+                    return Err(DecompileError::Unstructured{pc: j2 * 2, target: t2 * 2});
+                }
+            }
+        }
+
+        let n = instrs.len();
+        let mut live_in = vec![0u8; n];
+        loop {
+            let mut changed = false;
+            for i in (0..n).rev() {
+                let (instr, operand) = instrs[i];
+                let mut live_out = if i + 1 < n { live_in[i + 1] } else { 0 };
+                if instr == Instr::Jnz {
+                    live_out |= live_in[operand as usize / 2];
+                }
+                let (uses, defs) = uses_defs(instr, operand);
+                let new = uses | (live_out & !defs);
+                if new != live_in[i] {
+                    live_in[i] = new;
+                    changed = true;
+                }
+            }
+            if !changed { break; }
+        }
+
+        Ok(Decompiler{instrs, live_in})
+    }
+
+    fn live_at(&self, i: usize) -> u8 {
+        self.live_in.get(i).copied().unwrap_or(0)
+    }
+
+    fn flush(regs: &mut [Expr; REG_COUNT], live: u8, stmts: &mut Vec<Stmt>) {
+        let assigns = (0..REG_COUNT)
+            .filter(|&r| live & reg_bit(r) != 0 && regs[r] != Expr::Reg(r))
+            .map(|r| (r, regs[r].clone()))
+            .collect::<Vec<_>>();
+
+        if !assigns.is_empty() {
+            stmts.push(Stmt::Assign(assigns));
+        }
+
+        *regs = std::array::from_fn(Expr::Reg);
+    }
+
+    /// Decompiles instructions `lo..hi`, writing back registers in `live_out` at the end.
+    fn build(&self, lo: usize, hi: usize, live_out: u8) -> Vec<Stmt> {
+        let mut stmts = Vec::new();
+        let mut regs: [Expr; REG_COUNT] = std::array::from_fn(Expr::Reg);
+
+        let mut i = lo;
+        while i < hi {
+            // The outermost loop starting here is the one with the furthest jnz.
+            let loop_end = (i..hi).rev()
+                .find(|&j| self.instrs[j].0 == Instr::Jnz && self.instrs[j].1 as usize == i * 2);
+
+            if let Some(j) = loop_end {
+                Self::flush(&mut regs, self.live_at(i), &mut stmts);
+                stmts.push(Stmt::Loop(self.build(i, j, self.live_at(j))));
+                i = j + 1;
+                continue;
+            }
+
+            let (instr, operand) = self.instrs[i];
+            let combo = |regs: &[Expr; REG_COUNT]| match operand {
+                0..=3 => Expr::Lit(operand as MachineWord),
+                _ => regs[(operand - 4) as usize].clone(),
+            };
+
+            match instr {
+                Instr::Adv => regs[A] = Expr::shr(regs[A].clone(), combo(&regs)),
+                Instr::Bdv => regs[B] = Expr::shr(regs[A].clone(), combo(&regs)),
+                Instr::Cdv => regs[C] = Expr::shr(regs[A].clone(), combo(&regs)),
+                Instr::Bxl => regs[B] = Expr::xor(regs[B].clone(), Expr::Lit(operand as MachineWord)),
+                Instr::Bst => regs[B] = Expr::mod8(combo(&regs)),
+                Instr::Bxc => regs[B] = Expr::xor(regs[B].clone(), regs[C].clone()),
+                Instr::Out => stmts.push(Stmt::Out(Expr::mod8(combo(&regs)))),
+                Instr::Jnz => unreachable!("every jnz closes a loop"),
+            }
+            i += 1;
+        }
+
+        Self::flush(&mut regs, live_out, &mut stmts);
+        stmts
+    }
+}
+
+/// Structured pseudo-code for a program. Fails if the jumps don't form nested loops.
+pub fn decompile(machine_code: &[u8]) -> Result<Vec<Stmt>, DecompileError> {
+    let decompiler = Decompiler::new(machine_code)?;
+    Ok(decompiler.build(0, decompiler.instrs.len(), 0))
+}
+
+fn write_stmts(out: &mut String, stmts: &[Stmt], indent: usize) -> fmt::Result {
+    let pad = "    ".repeat(indent);
+    for stmt in stmts {
+        match stmt {
+            Stmt::Assign(assigns) if assigns.len() == 1 => {
+                let (r, e) = &assigns[0];
+                writeln!(out, "{pad}{} = {e};", REG_NAMES[*r])?;
+            },
+            Stmt::Assign(assigns) => {
+                // Only needs a tuple when one value feeds into another assignment.
+                let dependent = assigns.iter()
+                    .any(|(r1, e)| assigns.iter().any(|(r2, _)| r1 != r2 && e.uses(*r2)));
+                if dependent {
+                    let names = assigns.iter().map(|(r, _)| REG_NAMES[*r].to_string()).collect::<Vec<_>>();
+                    let exprs = assigns.iter().map(|(_, e)| e.to_string()).collect::<Vec<_>>();
+                    writeln!(out, "{pad}({}) = ({});", names.join(", "), exprs.join(", "))?;
+                } else {
+                    for (r, e) in assigns {
+                        writeln!(out, "{pad}{} = {e};", REG_NAMES[*r])?;
+                    }
+                }
+            },
+            Stmt::Out(e) => writeln!(out, "{pad}out({e});")?,
+            Stmt::Loop(body) => {
+                writeln!(out, "{pad}loop {{")?;
+                write_stmts(out, body, indent + 1)?;
+                writeln!(out, "{pad}    if A == 0 {{ break; }}")?;
+                writeln!(out, "{pad}}}")?;
+            },
+        }
+    }
+    Ok(())
+}
+
+/// Renders decompiled statements as a Rust-like function.
+pub fn to_pseudo_code(stmts: &[Stmt]) -> String {
+    let mut out = String::new();
+    out.push_str("fn program(mut A: u64, mut B: u64, mut C: u64) {\n");
+    write_stmts(&mut out, stmts, 1).unwrap();
+    out.push_str("}\n");
+    out
+}
+
+type CompiledExpr = Box<dyn Fn(&[MachineWord; REG_COUNT]) -> MachineWord + Send + Sync>;
+
+fn compile_expr(e: &Expr) -> CompiledExpr {
+    match e {
+        &Expr::Reg(r) => Box::new(move |regs| regs[r]),
+        &Expr::Lit(v) => Box::new(move |_| v),
+        Expr::Shr(a, b) => {
+            let (a, b) = (compile_expr(a), compile_expr(b));
+            Box::new(move |regs| {
+                let s = b(regs);
+                if s >= MachineWord::BITS as MachineWord { 0 } else { a(regs) >> s }
+            })
+        },
+        Expr::Xor(a, b) => {
+            let (a, b) = (compile_expr(a), compile_expr(b));
+            Box::new(move |regs| a(regs) ^ b(regs))
+        },
+        Expr::Mod8(a) => {
+            let a = compile_expr(a);
+            Box::new(move |regs| a(regs) % 8)
+        },
+    }
+}
+
+enum CompiledStmt {
+    Assign(Vec<(usize, CompiledExpr)>),
+    Out(CompiledExpr),
+    Loop(Vec<CompiledStmt>),
+}
+
+fn compile_stmts(stmts: &[Stmt]) -> Vec<CompiledStmt> {
+    stmts.iter()
+        .map(|s| match s {
+            Stmt::Assign(assigns) => CompiledStmt::Assign(assigns.iter().map(|(r, e)| (*r, compile_expr(e))).collect()),
+            Stmt::Out(e) => CompiledStmt::Out(compile_expr(e)),
+            Stmt::Loop(body) => CompiledStmt::Loop(compile_stmts(body)),
+        })
+        .collect()
+}
+
+/// A decompiled program turned into nested closures.
+pub struct CompiledProgram(Vec<CompiledStmt>);
+
+impl CompiledProgram {
+    pub fn new(stmts: &[Stmt]) -> Self {
+        CompiledProgram(compile_stmts(stmts))
+    }
+
+    /// Runs the program, passing each output to `emit`. Stops early and returns false as
+    /// soon as `emit` returns false. A loop that never outputs or halts will not return.
+    pub fn run_with(&self, registers: [MachineWord; REG_COUNT], mut emit: impl FnMut(u8) -> bool) -> bool {
+        fn exec(stmts: &[CompiledStmt], regs: &mut [MachineWord; REG_COUNT], emit: &mut dyn FnMut(u8) -> bool) -> bool {
+            for s in stmts {
+                match s {
+                    CompiledStmt::Assign(assigns) => {
+                        let old = *regs;
+                        for (r, e) in assigns {
+                            regs[*r] = e(&old);
+                        }
+                    },
+                    CompiledStmt::Out(e) => if !emit(e(regs) as u8) { return false; },
+                    CompiledStmt::Loop(body) => loop {
+                        if !exec(body, regs, emit) { return false; }
+                        if regs[A] == 0 { break; }
+                    },
+                }
+            }
+            true
+        }
+
+        let mut regs = registers;
+        exec(&self.0, &mut regs, &mut emit)
+    }
+
+    pub fn run(&self, registers: [MachineWord; REG_COUNT]) -> Vec<u8> {
+        let mut output = Vec::new();
+        self.run_with(registers, |v| { output.push(v); true });
+        output
+    }
+
+    /// Whether the program prints exactly `target`, stopping at the first mismatch.
+    pub fn produces(&self, registers: [MachineWord; REG_COUNT], target: &[u8]) -> bool {
+        let mut pos = 0;
+        let completed = self.run_with(registers, |v| {
+            let ok = target.get(pos) == Some(&v);
+            pos += 1;
+            ok
+        });
+        completed && pos == target.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn puzzle_shape() {
+        let machine_code = [2,4,1,5,7,5,1,6,0,3,4,3,5,5,3,0];
+        let stmts = decompile(&machine_code).unwrap();
+        assert_eq!(to_pseudo_code(&stmts), "\
+fn program(mut A: u64, mut B: u64, mut C: u64) {
+    loop {
+        out((A % 8 ^ 3 ^ A >> (A % 8 ^ 5)) % 8);
+        A = A >> 3;
+        if A == 0 { break; }
+    }
+}
+");
+
+        let program = Program{machine_code: machine_code.to_vec(), initial_registers: [0; REG_COUNT]};
+        let compiled = CompiledProgram::new(&stmts);
+        for a in (0..5000).chain([0x1234_5678_9abc, MachineWord::MAX]) {
+            let expected = run_program(&program, a, Vec::new());
+            assert_eq!(compiled.run([a, 0, 0]), expected);
+            assert!(compiled.produces([a, 0, 0], &expected));
+        }
+    }
+
+    #[test]
+    fn live_registers_are_written_back() {
+        // B carries over between iterations, and C is set up before the loop.
+        let machine_code = assemble("cdv 1\nbxc 0\nbxl 1\nout 5\nadv 1\njnz 2\nout 6").unwrap();
+        let stmts = decompile(&machine_code).unwrap();
+        assert_eq!(to_pseudo_code(&stmts), "\
+fn program(mut A: u64, mut B: u64, mut C: u64) {
+    C = A >> 1;
+    loop {
+        out((B ^ C ^ 1) % 8);
+        A = A >> 1;
+        B = B ^ C ^ 1;
+        if A == 0 { break; }
+    }
+    out(C % 8);
+}
+");
+
+        let program = Program{machine_code, initial_registers: [0; REG_COUNT]};
+        let compiled = CompiledProgram::new(&stmts);
+        for a in 0..2000 {
+            assert_eq!(compiled.run([a, 0, 0]), run_program(&program, a, Vec::new()));
+        }
+    }
+
+    #[test]
+    fn unstructured() {
+        assert_eq!(decompile(&[3, 4, 5, 4, 5, 5]), Err(DecompileError::Unstructured{pc: 0, target: 4}));
+        assert_eq!(decompile(&[5, 4, 3, 1]), Err(DecompileError::Unstructured{pc: 2, target: 1}));
+    }
+}
@@ -0,0 +1,482 @@
+use std::{collections::BTreeSet, fmt, str::FromStr};
+use itertools::Itertools;
+use num_derive::FromPrimitive;
+use num_traits::FromPrimitive;
+use thiserror::Error;
+
+pub mod decompile;
+pub mod symbolic;
+
+pub type MachineWord = u64;
+
+pub const REG_COUNT: usize = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, FromPrimitive)]
+pub enum RegNum {
+    A = 0,
+    B = 1,
+    C = 2
+}
+
+pub const A: usize = RegNum::A as usize;
+pub const B: usize = RegNum::B as usize;
+pub const C: usize = RegNum::C as usize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, FromPrimitive)]
+pub enum Instr {
+    Adv = 0,
+    Bxl = 1,
+    Bst = 2,
+    Jnz = 3,
+    Bxc = 4,
+    Out = 5,
+    Bdv = 6,
+    Cdv = 7
+}
+
+use Instr::*;
+
+const INSTR_NAMES: [(&str, Instr); 8] = [
+    ("adv", Adv),
+    ("bxl", Bxl),
+    ("bst", Bst),
+    ("jnz", Jnz),
+    ("bxc", Bxc),
+    ("out", Out),
+    ("bdv", Bdv),
+    ("cdv", Cdv),
+];
+
+impl FromStr for Instr {
+    type Err = MachineError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        INSTR_NAMES.iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(s))
+            .map(|(_, instr)| *instr)
+            .ok_or_else(|| MachineError::ParseError(format!("unknown instruction '{s}'")))
+    }
+}
+
+#[derive(Debug, Clone, Error, PartialEq, Eq)]
+pub enum MachineError {
+    #[error("Invalid opcode {opcode} at pc {pc}")]
+    InvalidOpcode{ pc: usize, opcode: u8 },
+    #[error("Invalid combo operand {operand} at pc {pc}")]
+    InvalidOperand{ pc: usize, operand: u8 },
+    #[error("Parsing error: {0}")]
+    ParseError(String),
+}
+
+pub struct DisplayableInstruction {
+    pub instr: u8,
+    pub operand: u8
+}
+
+impl fmt::Display for DisplayableInstruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Some(instr) = Instr::from_u8(self.instr) else {
+            return write!(f, "invalid opcode: {}", self.instr);
+        };
+
+        write!(f, "{:?} {}: ", instr, self.operand)?;
+
+        fn fmt_lit(out: &mut fmt::Formatter<'_>, operand: u8) -> fmt::Result {
+            write!(out, "#{}", operand)
+        }
+
+        fn fmt_combo(out: &mut fmt::Formatter<'_>, operand: u8) -> fmt::Result {
+            match operand {
+                0..=3 => fmt_lit(out, operand),
+                4..=6 => write!(out, "{}", (b'A' + operand - 4) as char),
+                _ => write!(out, "invalid operand: {}", operand),
+            }
+        }
+
+        let fmt_div = |out: &mut fmt::Formatter<'_>, dest| {
+            write!(out, "{dest} = A / (1 << ")?;
+            fmt_combo(out, self.operand)?;
+            write!(out, ")")
+        };
+
+        match instr {
+            Adv => fmt_div(f, 'A'),
+            Bdv => fmt_div(f, 'B'),
+            Cdv => fmt_div(f, 'C'),
+            Bxl => {write!(f, "B ^= ")?; fmt_lit(f, self.operand)},
+            Bst => {write!(f, "B = ")?; fmt_combo(f, self.operand)?; write!(f, " % 8")},
+            Jnz => {write!(f, "jnz ")?; fmt_lit(f, self.operand)},
+            Bxc => write!(f, "B ^= C"),
+            Out => {write!(f, "Out: ")?; fmt_combo(f, self.operand)?; write!(f, " % 8")},
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Program {
+    pub machine_code: Vec<u8>,
+    pub initial_registers: [MachineWord; REG_COUNT],
+}
+
+impl Program {
+    /// Parses the puzzle format: three `Register X: n` lines, a blank line and
+    /// a `Program: a,b,c,...` line.
+    pub fn parse(input: &str) -> Result<Self, MachineError> {
+        let parse_error = |msg: String| MachineError::ParseError(msg);
+
+        let mut line_iter = input.lines();
+        let mut initial_registers = [0 as MachineWord; REG_COUNT];
+        for (i, reg) in ['A', 'B', 'C'].into_iter().enumerate() {
+            let l = line_iter.next().ok_or_else(|| parse_error(format!("missing register {reg}")))?;
+            let val = l.strip_prefix(&format!("Register {reg}: "))
+                .and_then(|v| v.trim().parse::<MachineWord>().ok())
+                .ok_or_else(|| parse_error(format!("bad register line '{l}'")))?;
+            initial_registers[i] = val;
+        }
+
+        if line_iter.next().is_some_and(|l| !l.trim().is_empty()) {
+            return Err(parse_error("expected blank line after registers".to_string()));
+        }
+
+        let prog_line = line_iter.next().ok_or_else(|| parse_error("missing program line".to_string()))?;
+        let prog = prog_line.strip_prefix("Program: ")
+            .ok_or_else(|| parse_error(format!("bad program line '{prog_line}'")))?;
+
+        let machine_code = prog.trim().split(',')
+            .map(|s| s.parse::<u8>().ok().filter(|c| *c <= 7)
+                .ok_or_else(|| parse_error(format!("bad program value '{s}'"))))
+            .try_collect()?;
+
+        Ok(Program{machine_code, initial_registers})
+    }
+
+    pub fn disassemble(&self) -> String {
+        disassemble(&self.machine_code)
+    }
+}
+
+/// One line per instruction, e.g. `1: 1 5 Bxl 5: B ^= #5`. The output can be fed back
+/// into [`assemble`].
+pub fn disassemble(machine_code: &[u8]) -> String {
+    machine_code.chunks(2)
+        .enumerate()
+        .map(|(offs, instr)| {
+            let (instr, operand) = (instr[0], instr.get(1).copied().unwrap_or(0));
+            format!("{offs}: {instr} {operand} {}\n", DisplayableInstruction{instr, operand})
+        })
+        .collect()
+}
+
+/// Parses assembly back into machine code. Each non-blank line holds one instruction,
+/// either as a mnemonic and operand (`bxl 5`) or as the raw opcode and operand (`1 5`).
+/// The `offs:` prefix and pseudo-code suffix written by [`disassemble`] are skipped, the
+/// raw opcode is checked against the mnemonic when both are given, and `//` starts a comment.
+pub fn assemble(text: &str) -> Result<Vec<u8>, MachineError> {
+    let mut machine_code = Vec::new();
+
+    for (line_no, line) in text.lines().enumerate() {
+        let parse_error = |msg: &str| MachineError::ParseError(format!("line {}: {msg}: '{line}'", line_no + 1));
+
+        let mut body = line.split("//").next().unwrap().trim();
+        if body.is_empty() {
+            continue;
+        }
+
+        if let Some((offs, rest)) = body.split_once(':') {
+            if offs.trim().parse::<usize>().is_ok() {
+                body = rest.trim();
+            }
+        }
+
+        let mut tokens = body.split_whitespace().map(|t| t.trim_end_matches(':')).peekable();
+        let mut raw = Vec::new();
+        while let Some(v) = tokens.peek().and_then(|t| t.parse::<u8>().ok()) {
+            raw.push(v);
+            tokens.next();
+        }
+
+        let mnemonic = match tokens.next() {
+            Some(name) => {
+                let instr: Instr = name.parse().map_err(|_| parse_error("unknown instruction"))?;
+                let operand = tokens.next()
+                    .and_then(|t| t.parse::<u8>().ok())
+                    .ok_or_else(|| parse_error("missing operand"))?;
+                Some((instr as u8, operand))
+            },
+            None => None,
+        };
+
+        let (instr, operand) = match (raw.as_slice(), mnemonic) {
+            ([], Some(m)) => m,
+            (&[i, o], None) => (i, o),
+            (&[i, o], Some(m)) if (i, o) == m => m,
+            (&[_, _], Some(_)) => return Err(parse_error("machine code does not match mnemonic")),
+            _ => return Err(parse_error("expected an instruction and operand")),
+        };
+
+        if instr > 7 || operand > 7 {
+            return Err(parse_error("values must be 3 bits"));
+        }
+
+        machine_code.extend([instr, operand]);
+    }
+
+    Ok(machine_code)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MachineState {
+    pub registers: [MachineWord; REG_COUNT],
+    pub pc: usize
+}
+
+fn get_combo_operand(operand: u8, state: &MachineState) -> Result<MachineWord, MachineError>
+{
+    match operand {
+        0..=3 => Ok(operand as MachineWord),
+        4..=6 => Ok(state.registers[(operand - 4) as usize]),
+        _ => Err(MachineError::InvalidOperand{pc: state.pc, operand})
+    }
+}
+
+fn do_div_instr(operand: u8, state: &MachineState) -> Result<MachineWord, MachineError>
+{
+    let operand = get_combo_operand(operand, state)?;
+
+    // Shifting out every bit leaves zero, same as dividing by a huge power of two.
+    Ok(if operand >= MachineWord::BITS as MachineWord { 0 } else { state.registers[A] >> operand })
+}
+
+/// Executes the instruction at `state.pc`, returning `None` once the pc runs off the program.
+pub fn step_program(machine_code: &[u8], state: &MachineState, output: &mut Vec<u8>) -> Result<Option<MachineState>, MachineError>
+{
+    if state.pc + 1 >= machine_code.len() { return Ok(None) };
+
+    let opcode = machine_code[state.pc];
+    let instr = Instr::from_u8(opcode).ok_or(MachineError::InvalidOpcode{pc: state.pc, opcode})?;
+    let operand = machine_code[state.pc + 1];
+
+    let mut new_state = state.clone();
+    new_state.pc += 2;
+    match instr {
+        Adv => {new_state.registers[A] = do_div_instr(operand, state)?},
+        Bdv => {new_state.registers[B] = do_div_instr(operand, state)?},
+        Cdv => {new_state.registers[C] = do_div_instr(operand, state)?},
+        Bxl => {new_state.registers[B] ^= operand as MachineWord},
+        Bst => {new_state.registers[B] = get_combo_operand(operand, state)? % 8},
+        Jnz => {if new_state.registers[A] != 0 { new_state.pc = operand.into() }},
+        Bxc => {new_state.registers[B] ^= new_state.registers[C]},
+        Out => {output.push((get_combo_operand(operand, state)? % 8) as u8)}
+    };
+
+    Ok(Some(new_state))
+}
+
+/// Runs `program` to completion with register A replaced by `a_value`, appending to `output`.
+/// Panics on an invalid instruction; use [`Machine`] to get errors or bound the run.
+pub fn run_program(program: &Program, a_value: MachineWord, mut output: Vec<u8>) -> Vec<u8> {
+    let mut state = MachineState{registers: program.initial_registers, pc: 0};
+    state.registers[A] = a_value;
+
+    while let Some(new_state) = step_program(&program.machine_code, &state, &mut output).unwrap() {
+        state = new_state;
+    }
+
+    output
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceEntry {
+    pub state: MachineState,
+    pub instr: u8,
+    pub operand: u8,
+}
+
+impl fmt::Display for TraceEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "pc:{} A:{} B:{} C:{}  {}",
+            self.state.pc,
+            self.state.registers[A],
+            self.state.registers[B],
+            self.state.registers[C],
+            DisplayableInstruction{instr: self.instr, operand: self.operand})
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopReason {
+    Halted,
+    Breakpoint(usize),
+    StepLimit,
+}
+
+/// A stepping interpreter with breakpoints, an optional instruction trace and a step limit.
+#[derive(Debug, Clone)]
+pub struct Machine<'a> {
+    machine_code: &'a [u8],
+    state: MachineState,
+    output: Vec<u8>,
+    breakpoints: BTreeSet<usize>,
+    trace: Option<Vec<TraceEntry>>,
+    step_limit: Option<usize>,
+    steps: usize,
+}
+
+impl<'a> Machine<'a> {
+    pub fn new(machine_code: &'a [u8], registers: [MachineWord; REG_COUNT]) -> Self {
+        Machine {
+            machine_code,
+            state: MachineState{registers, pc: 0},
+            output: Vec::new(),
+            breakpoints: BTreeSet::new(),
+            trace: None,
+            step_limit: None,
+            steps: 0,
+        }
+    }
+
+    pub fn from_program(program: &'a Program) -> Self {
+        Self::new(&program.machine_code, program.initial_registers)
+    }
+
+    pub fn add_breakpoint(&mut self, pc: usize) {
+        self.breakpoints.insert(pc);
+    }
+
+    pub fn remove_breakpoint(&mut self, pc: usize) {
+        self.breakpoints.remove(&pc);
+    }
+
+    /// Stop with [`StopReason::StepLimit`] once `limit` instructions have run in total.
+    pub fn set_step_limit(&mut self, limit: Option<usize>) {
+        self.step_limit = limit;
+    }
+
+    pub fn enable_trace(&mut self) {
+        self.trace.get_or_insert_with(Vec::new);
+    }
+
+    pub fn trace(&self) -> &[TraceEntry] {
+        self.trace.as_deref().unwrap_or_default()
+    }
+
+    pub fn state(&self) -> &MachineState {
+        &self.state
+    }
+
+    pub fn state_mut(&mut self) -> &mut MachineState {
+        &mut self.state
+    }
+
+    pub fn output(&self) -> &[u8] {
+        &self.output
+    }
+
+    pub fn steps(&self) -> usize {
+        self.steps
+    }
+
+    pub fn is_halted(&self) -> bool {
+        self.state.pc + 1 >= self.machine_code.len()
+    }
+
+    /// Executes a single instruction. Returns false if the machine had already halted.
+    pub fn step(&mut self) -> Result<bool, MachineError> {
+        let pc = self.state.pc;
+        let Some(new_state) = step_program(self.machine_code, &self.state, &mut self.output)? else {
+            return Ok(false);
+        };
+
+        if let Some(trace) = self.trace.as_mut() {
+            trace.push(TraceEntry{
+                state: self.state.clone(),
+                instr: self.machine_code[pc],
+                operand: self.machine_code[pc + 1],
+            });
+        }
+
+        self.state = new_state;
+        self.steps += 1;
+        Ok(true)
+    }
+
+    /// Runs until the program halts, a breakpoint is reached or the step limit is hit.
+    /// A breakpoint on the current pc is stepped over, so calling `run` again resumes.
+    pub fn run(&mut self) -> Result<StopReason, MachineError> {
+        let mut first = true;
+        loop {
+            if self.is_halted() {
+                return Ok(StopReason::Halted);
+            }
+
+            if !first && self.breakpoints.contains(&self.state.pc) {
+                return Ok(StopReason::Breakpoint(self.state.pc));
+            }
+
+            if self.step_limit.is_some_and(|limit| self.steps >= limit) {
+                return Ok(StopReason::StepLimit);
+            }
+
+            self.step()?;
+            first = false;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE: &str = "Register A: 729\nRegister B: 0\nRegister C: 0\n\nProgram: 0,1,5,4,3,0";
+
+    #[test]
+    fn sample_output() {
+        let program = Program::parse(SAMPLE).unwrap();
+        let output = run_program(&program, program.initial_registers[A], Vec::new());
+        assert_eq!(output, [4,6,3,5,6,3,5,2,1,0]);
+    }
+
+    #[test]
+    fn disassemble_round_trip() {
+        let machine_code = vec![2,4,1,5,7,5,1,6,0,3,4,3,5,5,3,0];
+        let text = disassemble(&machine_code);
+        assert_eq!(assemble(&text).unwrap(), machine_code);
+
+        let hand_written = "bst 4\nbxl 5 // comment\n\n7 5\nOut 5\njnz 0";
+        assert_eq!(assemble(hand_written).unwrap(), [2,4,1,5,7,5,5,5,3,0]);
+
+        assert!(assemble("2 4 bxl 4").is_err());
+        assert!(assemble("foo 1").is_err());
+        assert!(assemble("bxl 9").is_err());
+    }
+
+    #[test]
+    fn step_limit_and_breakpoints() {
+        // A is never changed, so this loops forever.
+        let machine_code = assemble("out 4\njnz 0").unwrap();
+        let mut machine = Machine::new(&machine_code, [1, 0, 0]);
+        machine.set_step_limit(Some(100));
+        assert_eq!(machine.run(), Ok(StopReason::StepLimit));
+        assert_eq!(machine.steps(), 100);
+
+        let program = Program::parse(SAMPLE).unwrap();
+        let mut machine = Machine::from_program(&program);
+        machine.add_breakpoint(2);
+        machine.enable_trace();
+        assert_eq!(machine.run(), Ok(StopReason::Breakpoint(2)));
+        assert_eq!(machine.output(), []);
+        assert_eq!(machine.run(), Ok(StopReason::Breakpoint(2)));
+        assert_eq!(machine.output(), [4]);
+        machine.remove_breakpoint(2);
+        assert_eq!(machine.run(), Ok(StopReason::Halted));
+        assert_eq!(machine.output(), [4,6,3,5,6,3,5,2,1,0]);
+        assert_eq!(machine.trace().len(), machine.steps());
+    }
+
+    #[test]
+    fn invalid_operand() {
+        let machine_code = [5, 7];
+        let mut machine = Machine::new(&machine_code, [0, 0, 0]);
+        assert_eq!(machine.run(), Err(MachineError::InvalidOperand{pc: 0, operand: 7}));
+    }
+}
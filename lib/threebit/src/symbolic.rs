@@ -0,0 +1,343 @@
+//! Symbolic execution of a single loop iteration, used to find register A values that
+//! make a program print a given output.
+//!
+//! Every register bit is tracked as a constant, an exact copy of a bit of A at the start
+//! of the iteration, or an unknown function of some set of A bits. That is enough to tell
+//! how far A is shifted per iteration and which A bits each output depends on, without
+//! needing an SMT solver.
+
+use std::fmt;
+use num_traits::FromPrimitive;
+use thiserror::Error;
+
+use crate::*;
+
+const WORD_BITS: usize = MachineWord::BITS as usize;
+
+/// The inputs a symbolic bit depends on: a mask of A bits, plus whether it depends on
+/// the B or C value left over from the previous iteration.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Deps {
+    pub a_bits: u64,
+    pub carried: bool,
+}
+
+impl Deps {
+    fn union(self, other: Deps) -> Deps {
+        Deps{a_bits: self.a_bits | other.a_bits, carried: self.carried || other.carried}
+    }
+}
+
+impl fmt::Display for Deps {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut ranges: Vec<(usize, usize)> = Vec::new();
+        for i in (0..WORD_BITS).filter(|i| self.a_bits & (1 << i) != 0) {
+            match ranges.last_mut() {
+                Some((_, hi)) if *hi + 1 == i => *hi = i,
+                _ => ranges.push((i, i)),
+            }
+        }
+
+        let ranges = ranges.iter()
+            .map(|&(lo, hi)| if lo == hi { format!("A[{lo}]") } else { format!("A[{lo}..={hi}]") })
+            .collect::<Vec<_>>();
+
+        write!(f, "{}", if ranges.is_empty() { "none".to_string() } else { ranges.join(", ") })?;
+        if self.carried {
+            write!(f, " + carried B/C")?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SymBit {
+    Const(bool),
+    /// Exactly bit `n` of A at the start of the iteration.
+    ABit(u8),
+    Mixed(Deps),
+}
+
+use SymBit::*;
+
+impl SymBit {
+    pub fn deps(&self) -> Deps {
+        match *self {
+            Const(_) => Deps::default(),
+            ABit(n) => Deps{a_bits: 1 << n, carried: false},
+            Mixed(d) => d,
+        }
+    }
+
+    fn xor(self, other: SymBit) -> SymBit {
+        match (self, other) {
+            (Const(a), Const(b)) => Const(a ^ b),
+            (Const(false), x) | (x, Const(false)) => x,
+            (a, b) => Mixed(a.deps().union(b.deps())),
+        }
+    }
+}
+
+type SymWord = [SymBit; WORD_BITS];
+
+fn const_word(value: MachineWord) -> SymWord {
+    std::array::from_fn(|i| Const(value & (1 << i) != 0))
+}
+
+fn word_deps(word: &[SymBit]) -> Deps {
+    word.iter().fold(Deps::default(), |d, b| d.union(b.deps()))
+}
+
+fn const_value(word: &SymWord) -> Option<MachineWord> {
+    word.iter().enumerate().try_fold(0, |acc, (i, b)| match b {
+        Const(true) => Some(acc | 1 << i),
+        Const(false) => Some(acc),
+        _ => None,
+    })
+}
+
+fn low_bits(word: &SymWord, count: usize) -> SymWord {
+    std::array::from_fn(|i| if i < count { word[i] } else { Const(false) })
+}
+
+/// `a >> shift` where the shift amount may itself be symbolic.
+fn shift_right(a: &SymWord, shift: &SymWord) -> SymWord {
+    if let Some(s) = const_value(shift) {
+        return std::array::from_fn(|i| {
+            let src = i as MachineWord + s;
+            if src < WORD_BITS as MachineWord { a[src as usize] } else { Const(false) }
+        });
+    }
+
+    // Bound the shift by its highest bit that could be set.
+    let high = shift.iter().rposition(|b| *b != Const(false)).unwrap();
+    let max_shift = if high >= 7 { WORD_BITS } else { ((1usize << (high + 1)) - 1).min(WORD_BITS) };
+    let shift_deps = word_deps(shift);
+
+    std::array::from_fn(|i| {
+        let window = &a[i..(i + max_shift + 1).min(WORD_BITS)];
+        if window.iter().all(|b| *b == Const(false)) {
+            Const(false)
+        } else {
+            Mixed(word_deps(window).union(shift_deps))
+        }
+    })
+}
+
+#[derive(Debug, Error)]
+pub enum SolveError {
+    #[error("Unsupported program shape: {0}")]
+    UnsupportedShape(String),
+    #[error("Target length {len} is not a multiple of the {per_iteration} outputs per iteration")]
+    TargetLength{ len: usize, per_iteration: usize },
+    #[error(transparent)]
+    Machine(#[from] MachineError),
+}
+
+/// What one iteration of a `... jnz 0` loop does, in terms of A at the start of the iteration.
+#[derive(Debug, Clone)]
+pub struct LoopAnalysis {
+    /// If A at the end of the iteration is exactly `A >> shift`.
+    pub a_shift: Option<u32>,
+    /// Dependencies of each value printed in one iteration, in order.
+    pub outputs: Vec<Deps>,
+    /// Dependencies of A at the end of the iteration.
+    pub next_a: Deps,
+}
+
+impl LoopAnalysis {
+    /// Requires the program to be a straight-line body followed by a single `jnz 0`.
+    pub fn analyze(machine_code: &[u8]) -> Result<Self, SolveError> {
+        let instrs = machine_code.chunks(2)
+            .enumerate()
+            .map(|(i, c)| match c {
+                &[opcode, operand] => Instr::from_u8(opcode)
+                    .map(|instr| (instr, operand))
+                    .ok_or(MachineError::InvalidOpcode{pc: i * 2, opcode}),
+                _ => Err(MachineError::ParseError("odd program length".to_string())),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let Some(((Instr::Jnz, 0), body)) = instrs.split_last() else {
+            return Err(SolveError::UnsupportedShape("program does not end with jnz 0".to_string()));
+        };
+
+        if body.iter().any(|(instr, _)| *instr == Instr::Jnz) {
+            return Err(SolveError::UnsupportedShape("loop body contains a jump".to_string()));
+        }
+
+        let carried = Mixed(Deps{a_bits: 0, carried: true});
+        let mut regs: [SymWord; REG_COUNT] = [
+            std::array::from_fn(|i| ABit(i as u8)),
+            [carried; WORD_BITS],
+            [carried; WORD_BITS],
+        ];
+
+        let mut outputs = Vec::new();
+        for (pc, &(instr, operand)) in body.iter().enumerate() {
+            let combo = |regs: &[SymWord; REG_COUNT]| match operand {
+                0..=3 => Ok(const_word(operand as MachineWord)),
+                4..=6 => Ok(regs[(operand - 4) as usize]),
+                _ => Err(MachineError::InvalidOperand{pc: pc * 2, operand}),
+            };
+
+            match instr {
+                Instr::Adv => regs[A] = shift_right(&regs[A], &combo(&regs)?),
+                Instr::Bdv => regs[B] = shift_right(&regs[A], &combo(&regs)?),
+                Instr::Cdv => regs[C] = shift_right(&regs[A], &combo(&regs)?),
+                Instr::Bxl => {
+                    let lit = const_word(operand as MachineWord);
+                    regs[B] = std::array::from_fn(|i| regs[B][i].xor(lit[i]));
+                },
+                Instr::Bst => regs[B] = low_bits(&combo(&regs)?, 3),
+                Instr::Bxc => regs[B] = std::array::from_fn(|i| regs[B][i].xor(regs[C][i])),
+                Instr::Out => outputs.push(word_deps(&combo(&regs)?[0..3])),
+                Instr::Jnz => unreachable!(),
+            }
+        }
+
+        let a_shift = (0..WORD_BITS as u32).find(|&k| {
+            regs[A].iter().enumerate().all(|(i, b)| {
+                let src = i + k as usize;
+                *b == if src < WORD_BITS { ABit(src as u8) } else { Const(false) }
+            })
+        });
+
+        Ok(LoopAnalysis{a_shift, outputs, next_a: word_deps(&regs[A])})
+    }
+
+    /// Whether this is the shape the puzzle's part 2 relies on: A shifted by three bits per
+    /// iteration, one output per iteration, and nothing carried over in B or C.
+    pub fn is_three_bit(&self) -> bool {
+        self.a_shift == Some(3) && self.outputs.len() == 1 && self.outputs.iter().all(|d| !d.carried)
+    }
+}
+
+impl fmt::Display for LoopAnalysis {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.a_shift {
+            Some(k) => writeln!(f, "A = A >> {k} per iteration")?,
+            None => writeln!(f, "A is not a plain shift per iteration, depends on {}", self.next_a)?,
+        }
+
+        for (i, d) in self.outputs.iter().enumerate() {
+            writeln!(f, "output {i} depends on {d}")?;
+        }
+
+        if !self.is_three_bit() {
+            writeln!(f, "note: the 3-bits-per-output assumption does not hold")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Finds every value of A that makes `program` print exactly `target`, sorted ascending.
+///
+/// Works from the last iteration backwards: the final iteration only sees the top bits of A,
+/// and each earlier iteration adds `a_shift` lower bits. A candidate is kept only if running
+/// the real program on it reproduces the corresponding suffix of `target`.
+pub fn find_inputs(program: &Program, target: &[u8]) -> Result<Vec<MachineWord>, SolveError> {
+    let analysis = LoopAnalysis::analyze(&program.machine_code)?;
+
+    let Some(shift) = analysis.a_shift.filter(|k| (1..=16).contains(k)) else {
+        return Err(SolveError::UnsupportedShape(format!("A must be shifted by 1 to 16 bits per iteration\n{analysis}")));
+    };
+
+    if analysis.outputs.iter().any(|d| d.carried) {
+        return Err(SolveError::UnsupportedShape(format!("outputs depend on B or C from the previous iteration\n{analysis}")));
+    }
+
+    let per_iteration = analysis.outputs.len();
+    if per_iteration == 0 || !target.len().is_multiple_of(per_iteration) {
+        return Err(SolveError::TargetLength{len: target.len(), per_iteration});
+    }
+
+    let iterations = target.len() / per_iteration;
+    let step_limit = (iterations + 1) * program.machine_code.len();
+
+    let matches = |a_value: MachineWord, expected: &[u8]| -> Result<bool, MachineError> {
+        let mut machine = Machine::from_program(program);
+        machine.state_mut().registers[A] = a_value;
+        machine.set_step_limit(Some(step_limit));
+        Ok(machine.run()? == StopReason::Halted && machine.output() == expected)
+    };
+
+    let mut candidates = vec![0 as MachineWord];
+    for iteration in (0..iterations).rev() {
+        let expected = &target[iteration * per_iteration..];
+        let mut next = Vec::new();
+        for c in candidates {
+            if c.checked_shl(shift).is_none_or(|v| v >> shift != c) {
+                continue;
+            }
+
+            for low in 0..(1 << shift) {
+                let a_value = (c << shift) | low;
+                if matches(a_value, expected)? {
+                    next.push(a_value);
+                }
+            }
+        }
+        candidates = next;
+    }
+
+    candidates.sort();
+    candidates.dedup();
+    Ok(candidates)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn puzzle_shape() {
+        let machine_code = [2,4,1,5,7,5,1,6,0,3,4,3,5,5,3,0];
+        let analysis = LoopAnalysis::analyze(&machine_code).unwrap();
+        assert_eq!(analysis.a_shift, Some(3));
+        assert_eq!(analysis.outputs, [Deps{a_bits: 0x3ff, carried: false}]);
+        assert!(analysis.is_three_bit());
+
+        let program = Program{machine_code: machine_code.to_vec(), initial_registers: [0; REG_COUNT]};
+        let solutions = find_inputs(&program, &machine_code).unwrap();
+        assert!(!solutions.is_empty());
+        for a in solutions {
+            assert_eq!(run_program(&program, a, Vec::new()), machine_code);
+        }
+    }
+
+    #[test]
+    fn sample_quine() {
+        let program = Program::parse("Register A: 2024\nRegister B: 0\nRegister C: 0\n\nProgram: 0,3,5,4,3,0").unwrap();
+        let solutions = find_inputs(&program, &program.machine_code).unwrap();
+        assert_eq!(solutions.first(), Some(&117440));
+    }
+
+    #[test]
+    fn other_shapes() {
+        // Two bits per iteration and two outputs per iteration.
+        let machine_code = assemble("bst 4\nbxl 3\nout 5\nadv 2\nout 4\njnz 0").unwrap();
+        let analysis = LoopAnalysis::analyze(&machine_code).unwrap();
+        assert_eq!(analysis.a_shift, Some(2));
+        assert_eq!(analysis.outputs.len(), 2);
+        assert!(!analysis.is_three_bit());
+
+        let program = Program{machine_code, initial_registers: [0; REG_COUNT]};
+        let target = run_program(&program, 0b1101_1001_1110, Vec::new());
+        let solutions = find_inputs(&program, &target).unwrap();
+        assert!(solutions.contains(&0b1101_1001_1110));
+
+        // Shifting A by a register value is not a fixed shift.
+        let machine_code = assemble("bst 4\nadv 5\nout 5\njnz 0").unwrap();
+        let analysis = LoopAnalysis::analyze(&machine_code).unwrap();
+        assert_eq!(analysis.a_shift, None);
+        let program = Program{machine_code, initial_registers: [0; REG_COUNT]};
+        assert!(matches!(find_inputs(&program, &[1, 2]), Err(SolveError::UnsupportedShape(_))));
+
+        // Printing B before it is written depends on the previous iteration.
+        let machine_code = assemble("out 5\nbst 4\nadv 3\njnz 0").unwrap();
+        let analysis = LoopAnalysis::analyze(&machine_code).unwrap();
+        assert!(analysis.outputs[0].carried);
+    }
+}